@@ -0,0 +1,212 @@
+use std::io::Write;
+
+use crate::cli::{Command, Options};
+use crate::csv::CSVFile;
+use crate::export::Table;
+use crate::filter::Filter;
+use crate::print::write_table;
+use crate::stats::ColumnStatistics;
+use crate::types::TYPE_SAMPLE_SIZE;
+
+// Exit codes, besides 0. Invalid arguments make clap exit with 2.
+pub const EXIT_FAILURE: i32 = 1; // Nothing useful was printed: the file cannot be read, the output cannot be written, or the arguments do not fit the file
+pub const EXIT_MALFORMED: i32 = 3; // Everything was printed, but some rows were malformed (and listed on stderr)
+
+#[derive(Debug)]
+pub enum BatchError {
+    Invalid(String),
+    Output(std::io::Error),
+}
+impl std::fmt::Display for BatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            BatchError::Invalid(message) => write!(f, "{}", message),
+            BatchError::Output(error) => write!(f, "Cannot write output: {}.", error),
+        }
+    }
+}
+
+impl From<std::io::Error> for BatchError {
+    fn from(error: std::io::Error) -> Self {
+        BatchError::Output(error)
+    }
+}
+
+// Whoever reads the output has seen enough, like when piping into `head`. The CSV writer hides
+// the cause of its errors a level deeper.
+fn is_broken_pipe(error: &std::io::Error) -> bool {
+    let csv_error = error.get_ref().and_then(|inner| inner.downcast_ref::<csv::Error>());
+    error.kind() == std::io::ErrorKind::BrokenPipe
+        || csv_error.is_some_and(|csv_error| matches!(csv_error.kind(), csv::ErrorKind::Io(inner) if inner.kind() == std::io::ErrorKind::BrokenPipe))
+}
+
+// Runs the command without a terminal and returns the exit code.
+pub fn run(command: &Command, options: &Options) -> i32 {
+    finish(options, |csv, output| execute(command, options, csv, output))
+}
+
+// Prints the rows that pass the filter as a table, instead of showing them, and returns the exit code.
+pub fn print(options: &Options) -> i32 {
+    finish(options, |csv, output| {
+        let filter = parse_filter(options, csv)?;
+        csv.load_all();
+        let rows = matching_rows(csv, filter.as_ref(), None);
+        Ok(write_table(csv, &rows, options.max_column_width, !options.no_headers, output)?)
+    })
+}
+
+// The file is read once from start to end, so a row index would only leave a cache file behind.
+fn finish<F>(options: &Options, write: F) -> i32 where F: FnOnce(&mut CSVFile, &mut dyn Write) -> Result<(), BatchError> {
+    let mut csv = match options.open_csv_streamed() {
+        Ok(csv) => csv,
+        Err(error) => {
+            eprintln!("{}", error);
+            return EXIT_FAILURE;
+        }
+    };
+
+    let stdout = std::io::stdout();
+    let mut output = std::io::BufWriter::new(stdout.lock());
+    let result = write(&mut csv, &mut output).and_then(|_| Ok(output.flush()?));
+    match result {
+        Ok(()) => (),
+        Err(BatchError::Output(error)) if is_broken_pipe(&error) => return 0,
+        Err(error) => {
+            eprintln!("{}", error);
+            return EXIT_FAILURE;
+        }
+    }
+
+    if csv.error_count() > 0 {
+        for error in csv.errors() {
+            eprintln!("Malformed {}", error);
+        }
+        return EXIT_MALFORMED;
+    }
+    0
+}
+
+fn parse_filter(options: &Options, csv: &CSVFile) -> Result<Option<Filter>, BatchError> {
+    match &options.filter {
+        Some(text) => Ok(Some(Filter::parse(text, csv).map_err(|error| BatchError::Invalid(error.to_string()))?)),
+        None => Ok(None),
+    }
+}
+
+fn execute(command: &Command, options: &Options, csv: &mut CSVFile, output: &mut dyn Write) -> Result<(), BatchError> {
+    let filter = parse_filter(options, csv)?;
+    let mut dialect = options.dialect();
+    if let Some(delimiter) = &options.export_delimiter {
+        dialect.delimiter = delimiter.as_u8();
+    }
+    let headers = !options.no_headers;
+
+    match command {
+        Command::Stats(stats) => {
+            let columns = resolve_columns(csv, &stats.columns)?;
+            csv.load_all();
+            let rows = matching_rows(csv, filter.as_ref(), None);
+            write_statistics(csv, &rows, columns, output)
+        }
+        Command::Head(head) => {
+            // Columns are typed from as many rows as when converting the whole file, so that
+            // values are written the same way.
+            csv.ensure_loaded(0..TYPE_SAMPLE_SIZE);
+            let rows = matching_rows(csv, filter.as_ref(), Some(head.rows));
+            let table = Table { csv, rows, columns: (0..csv.column_count()).collect(), headers };
+            Ok(table.write(head.to, &dialect, output)?)
+        }
+        Command::Select(select) => {
+            let names: Vec<String> = select.columns.split(',').map(|name| name.trim().to_owned()).collect();
+            let columns = resolve_columns(csv, &names)?;
+            let rows = matching_rows(csv, filter.as_ref(), None);
+            let table = Table { csv, rows, columns, headers };
+            Ok(table.write(select.to, &dialect, output)?)
+        }
+        Command::Convert(convert) => {
+            let rows = matching_rows(csv, filter.as_ref(), None);
+            let table = Table { csv, rows, columns: (0..csv.column_count()).collect(), headers };
+            Ok(table.write(convert.to, &dialect, output)?)
+        }
+    }
+}
+
+// Columns are named by header or by `#N`, their 1-based number. No names at all means all columns.
+fn resolve_columns(csv: &CSVFile, names: &[String]) -> Result<Vec<usize>, BatchError> {
+    if names.is_empty() {
+        return Ok((0..csv.column_count()).collect());
+    }
+    names.iter()
+        .map(|name| {
+            let number = name.strip_prefix('#').and_then(|number| number.parse::<usize>().ok());
+            match number {
+                Some(number) if number >= 1 && number <= csv.column_count() => Ok(number - 1),
+                Some(_) => Err(BatchError::Invalid(format!("No column {}.", name))),
+                None => csv.column_named(name).ok_or_else(|| BatchError::Invalid(format!("No column named \"{}\".", name))),
+            }
+        })
+        .collect()
+}
+
+// Reads only as far as it takes to find `limit` rows.
+fn matching_rows(csv: &mut CSVFile, filter: Option<&Filter>, limit: Option<usize>) -> Vec<usize> {
+    let mut rows = Vec::new();
+    let mut row_index = 0;
+    while limit.map_or(true, |limit| rows.len() < limit) {
+        csv.ensure_loaded(row_index..row_index + 1);
+        if !csv.loaded_rows().contains(&row_index) {
+            break;
+        }
+        if filter.map_or(true, |filter| filter.matches(csv, row_index)) {
+            rows.push(row_index);
+        }
+        row_index += 1;
+    }
+    rows
+}
+
+fn write_statistics(csv: &CSVFile, rows: &[usize], columns: Vec<usize>, output: &mut dyn Write) -> Result<(), BatchError> {
+    for (position, &column_index) in columns.iter().enumerate() {
+        let column = match csv.get_column(column_index) {
+            Some(column) => column,
+            None => continue,
+        };
+        let statistics = ColumnStatistics::compute(column, rows, |_, _| true)
+            .expect("Statistics are only cancelled through the progress callback");
+        if position > 0 {
+            writeln!(output)?;
+        }
+        writeln!(output, "{}", csv.column_name(column_index))?;
+        for line in statistics.lines() {
+            match line.as_str() {
+                "" => writeln!(output)?,
+                line => writeln!(output, "  {}", line)?,
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Clap;
+    use crate::csv::tests::unloaded;
+
+    fn run_on(arguments: &[&str], text: &str) -> String {
+        let mut options = Options::try_parse_from(arguments).unwrap();
+        let command = options.command.take().unwrap();
+        let mut output = Vec::new();
+        execute(&command, &options, &mut unloaded(text), &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn head_types_columns_like_convert() {
+        let text = "name,age\na,30\nb,N/A\nc,5\n";
+        let head = run_on(&["hamstercsv", "head", "-n", "1", "--to", "ndjson"], text);
+        let convert = run_on(&["hamstercsv", "convert", "--to", "ndjson"], text);
+        assert_eq!(head, "{\"name\": \"a\", \"age\": \"30\"}\n");
+        assert!(convert.starts_with(&head));
+    }
+}
@@ -1,4 +1,5 @@
 use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
 use std::str::FromStr;
 use std::fmt::Formatter;
 use std::fmt::Display;
@@ -6,44 +7,158 @@ use std::path::PathBuf;
 
 use csv;
 
+use crate::csv::CSVFile;
+use crate::export::Format;
+use crate::index::RowIndex;
+
 use clap::Clap;
 
 #[derive(Clap, Debug)]
 pub struct Options {
-    #[clap(long)]
+    #[clap(long, global(true))]
     pub no_headers: bool,
 
-    #[clap(short, long, default_value(","), about(""))]
+    #[clap(short, long, default_value(","), about(""), global(true))]
     pub column_delimiter: AsciiCharacter,
 
-    #[clap(short, long, default_value("CRLF"))]
+    #[clap(short, long, default_value("CRLF"), global(true))]
     pub row_teminator: Terminator,
 
-    #[clap(short, long)]
+    #[clap(short, long, global(true))]
     pub escape: Option<AsciiCharacter>,
 
-    #[clap(long)]
+    #[clap(long, global(true))]
     pub comment: Option<AsciiCharacter>,
 
-    #[clap(long, default_value("all"))]
+    #[clap(long, default_value("all"), global(true))]
     pub trim_whitespace: Trim,
 
-    #[clap(long, default_value("\""))]
+    #[clap(long, default_value("\""), global(true))]
     pub quote: AsciiCharacter,
 
-    #[clap(long)]
+    #[clap(long, global(true))]
     pub ignore_quotes: bool,
 
-    #[clap(long)]
+    #[clap(long, global(true))]
     pub ignore_double_quotes: bool, 
 
-    #[clap(long)]
+    #[clap(long, global(true))]
     pub each_row_same_length: bool, // flexible length records by default by default
 
+    #[clap(long, default_value("40"))]
+    pub max_column_width: usize,
+
+    #[clap(long, default_value("0"))]
+    pub frozen_columns: usize, // Leftmost columns that stay on screen when scrolling right
+
+    #[clap(long)]
+    pub row_numbers: bool, // Show record numbers in a gutter
+
+    #[clap(long)]
+    pub line_numbers: bool, // Show line numbers in the file next to record numbers
+
     #[clap(long, default_value("en_US.UTF-8"))]
     pub locale: String,
 
-    pub path: PathBuf,
+    #[clap(long, global(true))]
+    pub filter: Option<String>, // Show only rows matching this expression, e.g. `status != 200 && latency_ms > 500`
+
+    #[clap(long)]
+    pub print: bool, // Print the table to stdout instead of showing it, as when stdout is not a terminal
+
+    #[clap(long)]
+    pub export: Option<Format>, // Write the rows (that pass the filter) to stdout in this format instead of showing them
+
+    #[clap(long, global(true))]
+    pub export_delimiter: Option<AsciiCharacter>, // For CSV exports, instead of the column delimiter of the file
+
+    #[clap(subcommand)]
+    pub command: Option<Command>, // Print something about the file instead of showing it
+
+    pub path: Option<PathBuf>, // "-" or no path reads from stdin
+}
+
+// Batch commands, which print to stdout and do not need a terminal. The dialect options and
+// `--filter` apply to them too.
+#[derive(Clap, Debug)]
+pub enum Command {
+    #[clap(about("Print statistics of each column"))]
+    Stats(StatsOptions),
+    #[clap(about("Print the first rows"))]
+    Head(HeadOptions),
+    #[clap(about("Print some of the columns"))]
+    Select(SelectOptions),
+    #[clap(about("Print the file in another format"))]
+    Convert(ConvertOptions),
+}
+
+#[derive(Clap, Debug)]
+pub struct StatsOptions {
+    #[clap(long("column"), number_of_values(1), multiple_occurrences(true))]
+    pub columns: Vec<String>, // Headers or `#N` column numbers, one per `--column`, all columns by default
+
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Clap, Debug)]
+pub struct HeadOptions {
+    #[clap(short('n'), long, default_value("10"))]
+    pub rows: usize,
+
+    #[clap(long, default_value("csv"))]
+    pub to: Format,
+
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Clap, Debug)]
+pub struct SelectOptions {
+    pub columns: String, // Comma-separated headers or `#N` column numbers, in the order to print them
+
+    #[clap(long, default_value("csv"))]
+    pub to: Format,
+
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Clap, Debug)]
+pub struct ConvertOptions {
+    #[clap(long)]
+    pub to: Format,
+
+    pub path: Option<PathBuf>,
+}
+
+impl Command {
+    pub fn path(&self) -> Option<&PathBuf> {
+        match self {
+            Command::Stats(options) => options.path.as_ref(),
+            Command::Head(options) => options.path.as_ref(),
+            Command::Select(options) => options.path.as_ref(),
+            Command::Convert(options) => options.path.as_ref(),
+        }
+    }
+}
+
+pub enum Input {
+    Stdin(std::io::Stdin),
+    File(File),
+}
+impl Read for Input {
+    fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Input::Stdin(stdin) => stdin.read(buffer),
+            Input::File(file) => file.read(buffer),
+        }
+    }
+}
+impl Seek for Input {
+    fn seek(&mut self, position: SeekFrom) -> std::io::Result<u64> {
+        match self {
+            Input::Stdin(_) => Err(std::io::Error::new(std::io::ErrorKind::Other, "Cannot seek in stdin.")),
+            Input::File(file) => file.seek(position),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -134,8 +249,113 @@ impl std::fmt::Display for TerminatorParseError {
     }
 }
 
+// How CSV is written, as far as it can be told from how it was read.
+#[derive(Debug, Clone)]
+pub struct Dialect {
+    pub delimiter: u8,
+    pub terminator: csv::Terminator,
+    pub quote: u8,
+    pub quoting: bool,
+    pub double_quote: bool,
+    pub escape: Option<u8>,
+    pub comment: Option<u8>,
+}
+impl Default for Dialect {
+    fn default() -> Self {
+        Dialect { delimiter: b',', terminator: csv::Terminator::Any(b'\n'), quote: b'"', quoting: true, double_quote: true, escape: None, comment: None }
+    }
+}
+impl Dialect {
+    // Rows keep the number of fields they have, even if that varies.
+    pub fn build_writer(&self) -> csv::WriterBuilder {
+        let mut builder = csv::WriterBuilder::new();
+
+        builder
+            .delimiter(self.delimiter)
+            .terminator(self.terminator)
+            .quote(self.quote)
+            .quote_style(if self.quoting { csv::QuoteStyle::Necessary } else { csv::QuoteStyle::Never })
+            .double_quote(self.double_quote && self.escape.is_none())
+            .comment(self.comment)
+            .flexible(true);
+        if let Some(escape) = self.escape {
+            builder.escape(escape);
+        }
+
+        builder
+    }
+}
+
+#[derive(Debug)]
+pub struct OpenError(PathBuf, std::io::Error);
+impl std::fmt::Display for OpenError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), std::fmt::Error> { 
+        write!(f, "Cannot open CSV file {:?}: {}.", self.0, self.1)
+    }
+}
+
 impl Options {
-    pub fn build_reader(&self) -> csv::Reader<File> {
+    pub fn reads_from_stdin(&self) -> bool {
+        match &self.path {
+            None => true,
+            Some(path) => path.as_os_str() == "-",
+        }
+    }
+
+    pub fn open_input(&self) -> Result<Input, OpenError> {
+        match &self.path {
+            Some(path) if !self.reads_from_stdin() => File::open(path)
+                .map(Input::File)
+                .map_err(|error| OpenError(path.clone(), error)),
+            _ => Ok(Input::Stdin(std::io::stdin())),
+        }
+    }
+
+    // Only regular files have a meaningful size, pipes and stdin do not.
+    pub fn input_size(&self) -> Option<u64> {
+        match &self.path {
+            Some(path) if !self.reads_from_stdin() => std::fs::metadata(path).ok()
+                .filter(|metadata| metadata.is_file())
+                .map(|metadata| metadata.len()),
+            _ => None,
+        }
+    }
+
+    // Everything that decides where records start, so that a cached row index is not reused with a different dialect.
+    pub fn dialect_key(&self) -> Vec<u8> {
+        let terminator = match self.row_teminator.as_csv_terminator() {
+            csv::Terminator::Any(byte) => byte,
+            _ => 0,
+        };
+        vec![
+            !self.no_headers as u8,
+            self.column_delimiter.as_u8(),
+            terminator,
+            self.escape.as_ref().map_or(0, |c| c.as_u8()),
+            self.comment.as_ref().map_or(0, |c| c.as_u8()),
+            self.quote.as_u8(),
+            self.ignore_quotes as u8,
+            self.ignore_double_quotes as u8,
+        ]
+    }
+
+    // Regular files get a row index (cached next to them), anything else is streamed.
+    pub fn open_csv(&self) -> Result<CSVFile, OpenError> {
+        let csv = match &self.path {
+            Some(path) if self.input_size().is_some() => {
+                CSVFile::indexed(self.build_reader()?, RowIndex::open(path, self.dialect_key()))
+            }
+            _ => CSVFile::from(self.build_reader()?),
+        };
+        Ok(csv.with_input_size(self.input_size()).with_trim(self.trim_whitespace.as_csv_trim()))
+    }
+
+    // Reads the file from start to end without a row index, for when nothing jumps around in it.
+    pub fn open_csv_streamed(&self) -> Result<CSVFile, OpenError> {
+        Ok(CSVFile::from(self.build_reader()?).with_trim(self.trim_whitespace.as_csv_trim()))
+    }
+
+    pub fn build_reader(&self) -> Result<csv::Reader<Input>, OpenError> {
         let mut builder = csv::ReaderBuilder::new();
 
         builder
@@ -147,11 +367,41 @@ impl Options {
             .quote(self.quote.as_u8())
             .quoting(!self.ignore_quotes)                        
             .double_quote(!self.ignore_double_quotes)
-            .trim(self.trim_whitespace.as_csv_trim())
+            .trim(csv::Trim::None) // CSVFile trims, and keeps track of whether that changed anything
             .flexible(!self.each_row_same_length);
 
-        builder
-            .from_path(&self.path)
-            .expect(&format!("Cannot open CSV file {:?}.", self.path))
+        Ok(builder.from_reader(self.open_input()?))
+    }
+
+    // The dialect the file was read with, to write it back the same way.
+    pub fn dialect(&self) -> Dialect {
+        Dialect {
+            delimiter: self.column_delimiter.as_u8(),
+            terminator: self.written_terminator(),
+            quote: self.quote.as_u8(),
+            quoting: !self.ignore_quotes,
+            double_quote: !self.ignore_double_quotes,
+            escape: self.escape.as_ref().map(|c| c.as_u8()),
+            comment: self.comment.as_ref().map(|c| c.as_u8()),
+        }
+    }
+
+    // The default terminator reads any line ending, so write the one the file already uses.
+    fn written_terminator(&self) -> csv::Terminator {
+        let terminator = self.row_teminator.as_csv_terminator();
+        if let csv::Terminator::Any(_) = terminator {
+            return terminator;
+        }
+        let mut start = Vec::new();
+        if let Some(path) = self.path.as_ref().filter(|_| !self.reads_from_stdin()) {
+            if let Ok(file) = File::open(path) {
+                let _ = file.take(64 * 1024).read_to_end(&mut start);
+            }
+        }
+        match start.iter().position(|&byte| byte == b'\n' || byte == b'\r') {
+            Some(position) if start[position] == b'\r' && start.get(position + 1) == Some(&b'\n') => csv::Terminator::CRLF,
+            Some(position) => csv::Terminator::Any(start[position]),
+            None => csv::Terminator::Any(b'\n'),
+        }
     }
 }
\ No newline at end of file
@@ -1,4 +1,12 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
 use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+use crate::index::{RowIndex, RowOffset};
+use crate::types::{ColumnType, TypeTally, TYPE_SAMPLE_SIZE};
 
 pub const ELIPSIS: &'static str = "…";
 pub const PAGE: &'static str = "⤶"; //"▼";
@@ -10,40 +18,71 @@ pub trait MaleableUnicode<'a>: Sized {
     fn cut_or_pad_to(&'a self, dimensions: Self::Dimensions, pad_with: &'static str) -> Self::Into;
 }
 
+// Terminal cells taken up by a grapheme: two for wide (CJK, most emoji), none for lone combining
+// marks. Emoji sequences joined into a single grapheme are still drawn as one wide character.
+pub fn grapheme_width(grapheme: &str) -> usize {
+    std::cmp::min(grapheme.width(), 2)
+}
+
+pub fn display_width(text: &str) -> usize {
+    text.graphemes(true).map(grapheme_width).sum()
+}
+
+// Columns are as wide as their widest value, up to `max_column_width`, but never empty.
+pub fn fit_width(content_width: usize, max_column_width: usize) -> usize {
+    std::cmp::max(std::cmp::min(content_width, max_column_width), 1)
+}
+
+// Moves the padding from the end of a line (cut or padded already) to the front.
+pub fn align_right(line: &mut Vec<&str>) {
+    let padding = line.iter().rev().take_while(|grapheme| **grapheme == PADDING).count();
+    line.rotate_right(padding);
+}
+
+// Replaces as many graphemes at the end of a line (cut or padded to `width` already) as it takes
+// to fit the single-cell `marker`, and pads whatever is left over, so the line stays as wide.
+fn end_with(line: &mut Vec<&str>, marker: &'static str, pad_with: &'static str) {
+    let mut freed = 0;
+    while freed < 1 {
+        match line.pop() {
+            Some(grapheme) => freed += grapheme_width(grapheme),
+            None => return,
+        }
+    }
+    line.push(marker);
+    for _ in 1..freed {
+        line.push(pad_with);
+    }
+}
+
 impl<'a> MaleableUnicode<'a> for String {
     type Into = Vec<&'a str>;
     type Dimensions = usize;
     fn cut_or_pad_to(&'a self, width: Self::Dimensions, pad_with: &'static str) -> Self::Into {
         log::info!("cut_or_pad_to<String> {:?} {:?} {:?}", self, width, pad_with);
-        log::info!("graphemes {:?}", self.graphemes(true).collect::<Vec<&str>>());
-        log::info!("graphemes[n    ] {:?}", self.graphemes(true).take(10).collect::<Vec<&str>>());
-        log::info!("graphemes[n + 1] {:?}", self.graphemes(true).take(10 + 1).collect::<Vec<&str>>());
 
-        // Grab one extra grapheme to check if there are too many
-        let mut graphemes: Vec<&str> = self.graphemes(true)
-            .take(width + 1)
-            .collect();            
-
-        let grapheme_count = graphemes.len();
-
-        log::info!("graphemes_count {:?}", grapheme_count);
-        if grapheme_count > width {
-
-            // Remove the extra
-            graphemes.pop();
+        // Take graphemes while they fit, then check if there are any left over
+        let mut graphemes: Vec<&str> = Vec::new();
+        let mut used = 0;
+        let mut overlong = false;
+        for grapheme in self.graphemes(true) {
+            let grapheme_width = grapheme_width(grapheme);
+            if used + grapheme_width > width {
+                overlong = true;
+                break;
+            }
+            graphemes.push(grapheme);
+            used += grapheme_width;
+        }
 
-            // Replace last fitting charactewr with overlong row indicator
-            graphemes.pop();
-            graphemes.push(ELIPSIS);
+        // A wide grapheme that does not fit leaves a gap, filled with padding
+        for _ in used..width {
+            graphemes.push(pad_with);
         }
-        log::info!("graphemes => {:?}", graphemes);
 
-        // Append padding, if needed
-        log::info!("w - gc {:?} - {:?}", width, grapheme_count);
-        if width > grapheme_count {
-            for _ in 0..(width - grapheme_count) {            
-                graphemes.push(pad_with);
-            }
+        // Replace the last fitting cell with overlong row indicator
+        if overlong {
+            end_with(&mut graphemes, ELIPSIS, pad_with);
         }
 
         log::info!("graphemes => {:?}", graphemes);
@@ -79,8 +118,7 @@ impl<'a> MaleableUnicode<'a> for CSVItem {
 
             // Replace last fitting row with one that has an overlong indicator
             let mut last_row = rows.pop().unwrap();
-            last_row.pop();
-            last_row.push(PAGE);
+            end_with(&mut last_row, PAGE, pad_with);
             rows.push(last_row);
         }
 
@@ -102,6 +140,25 @@ impl<'a> MaleableUnicode<'a> for CSVItem {
     }
 }
 
+impl CSVItem {
+    pub fn lines(&self) -> impl Iterator<Item=&str> {
+        self.rows.iter().map(|row| row.as_str())
+    }
+    pub fn text(&self) -> String {
+        self.rows.join("\n")
+    }
+    pub fn width(&self) -> usize {
+        self.width
+    }
+    pub fn height(&self) -> usize {
+        self.height
+    }
+    // Rows shorter than others have no values for the last columns, as opposed to empty values.
+    pub fn is_missing(&self) -> bool {
+        self.rows.is_empty()
+    }
+}
+
 impl Default for CSVItem {
     fn default() -> Self {
         CSVItem { rows: vec![], width: 0, height: 0 }
@@ -141,7 +198,7 @@ impl From<&str> for CSVItem {
                 (_, grapheme) => { 
                     row.push_str(grapheme);
                     previous = current;
-                    width += 1;
+                    width += grapheme_width(grapheme);
                 }
             }            
         }
@@ -163,35 +220,143 @@ impl From<&str> for CSVItem {
 pub struct CSVColumn {
     header: String,
     values: Vec<CSVItem>,
+    first_row: usize, // Row index of `values[0]`, non-zero after jumping around an indexed file
     max_width: usize,
+    widest: usize, // How many of the values (and the header) are `max_width` wide
     max_height: usize,
-    // type?
+    tallest: usize, // How many of the values are `max_height` tall
+    types: TypeTally, // Sampled from the first values loaded, which survive jumping around
 }
 
 impl Default for CSVColumn {
     fn default() -> Self {
-        CSVColumn { header: String::default(), values: Vec::new(), max_width: 0, max_height: 0 }
+        CSVColumn::from_header(String::default())
     }
 }
 
 impl CSVColumn {
     pub fn from_header(header: String) -> Self {
-        CSVColumn { header, values: Vec::new(), max_width: 0, max_height: 0 }
+        let max_width = display_width(&header);
+        CSVColumn { header, values: Vec::new(), first_row: 0, max_width, widest: 1, max_height: 0, tallest: 0, types: TypeTally::default() }
     }
     pub fn header(&self) -> &str {
         self.header.as_str()
     }
-    fn set_value(&mut self, index: usize, value: CSVItem) {
+    pub fn set_header(&mut self, header: String) {
+        let width = display_width(&header);
+        let previous = std::mem::replace(&mut self.header, header);
+        self.fit(width, 0);
+        self.unfit(display_width(&previous), 0);
+    }
+    // Replaces the value at `index`, or adds it after the loaded ones (with missing values in between).
+    pub fn set_value(&mut self, index: usize, value: CSVItem) {
+        let index = index - self.first_row;
+        if index < self.values.len() {
+            self.fit(value.width, value.height);
+            let previous = std::mem::replace(&mut self.values[index], value);
+            self.unfit(previous.width, previous.height);
+            if index < TYPE_SAMPLE_SIZE {
+                self.types.remove(&previous.text());
+                self.types.add(&self.values[index].text());
+            }
+            return;
+        }
+        self.fit(value.width, value.height);
+        self.types.add(&value.text());
         while index > self.values.len() {
-            self.values.push(CSVItem::default());            
+            self.push_missing();
         } 
         self.values.push(value);
     }
+    pub fn insert_value(&mut self, index: usize, value: CSVItem) {
+        let index = index - self.first_row;
+        while index > self.values.len() {
+            self.push_missing();
+        }
+        self.fit(value.width, value.height);
+        if index < TYPE_SAMPLE_SIZE {
+            // The value shifted out of the sample is no longer counted.
+            if let Some(last) = self.values.get(TYPE_SAMPLE_SIZE - 1) {
+                self.types.remove(&last.text());
+            }
+            self.types.add(&value.text());
+        }
+        self.values.insert(index, value);
+    }
+    pub fn remove_value(&mut self, index: usize) -> Option<CSVItem> {
+        let index = index.checked_sub(self.first_row)?;
+        if index >= self.values.len() {
+            return None;
+        }
+        let value = self.values.remove(index);
+        self.unfit(value.width, value.height);
+        if index < TYPE_SAMPLE_SIZE {
+            self.types.remove(&value.text());
+            // The value shifted into the sample counts now.
+            if let Some(last) = self.values.get(TYPE_SAMPLE_SIZE - 1) {
+                self.types.add(&last.text());
+            }
+        }
+        Some(value)
+    }
+    fn push_missing(&mut self) {
+        self.fit(0, 0);
+        self.values.push(CSVItem::default());
+    }
+    // Sizes only ever grow as values load, but an edit can also shrink them.
+    fn fit(&mut self, width: usize, height: usize) {
+        match width.cmp(&self.max_width) {
+            Ordering::Greater => { self.max_width = width; self.widest = 1; }
+            Ordering::Equal => self.widest += 1,
+            Ordering::Less => {}
+        }
+        match height.cmp(&self.max_height) {
+            Ordering::Greater => { self.max_height = height; self.tallest = 1; }
+            Ordering::Equal => self.tallest += 1,
+            Ordering::Less => {}
+        }
+    }
+    // Measures again only once the last of the widest or tallest values is gone.
+    fn unfit(&mut self, width: usize, height: usize) {
+        if width == self.max_width {
+            self.widest = self.widest.saturating_sub(1);
+        }
+        if height == self.max_height {
+            self.tallest = self.tallest.saturating_sub(1);
+        }
+        if self.widest == 0 || self.tallest == 0 {
+            self.measure_sizes();
+        }
+    }
+    fn measure_sizes(&mut self) {
+        self.max_width = display_width(&self.header);
+        self.widest = 1;
+        self.max_height = 0;
+        self.tallest = 0;
+        for index in 0..self.values.len() {
+            let (width, height) = (self.values[index].width, self.values[index].height);
+            self.fit(width, height);
+        }
+    }
+    // After jumping around, the sizes and types also count values that are no longer loaded.
+    fn measure(&mut self) {
+        self.measure_sizes();
+        self.types = TypeTally::default();
+        for value in self.values.iter() {
+            self.types.add(&value.text());
+        }
+    }
+    fn clear_values(&mut self, first_row: usize) {
+        self.values.clear();
+        self.first_row = first_row;
+        self.widest = (display_width(&self.header) == self.max_width) as usize;
+        self.tallest = 0;
+    }
     // fn push_value(&mut self, value: CSVItem) {        
     //     self.values.push(value);
     // }
     pub fn value(&self, index: usize) -> Option<&CSVItem> {
-        self.values.get(index)
+        index.checked_sub(self.first_row).and_then(|index| self.values.get(index))
     }    
     pub fn values(&self) -> impl Iterator<Item=&CSVItem> {
         self.values.iter()
@@ -199,16 +364,244 @@ impl CSVColumn {
     pub fn row_count(&self) -> usize {
         self.values.len()
     }
+    // Widest value (or header) loaded so far, in graphemes.
+    pub fn max_width(&self) -> usize {
+        self.max_width
+    }
+    // Tallest value loaded so far, in lines.
+    pub fn max_height(&self) -> usize {
+        self.max_height
+    }
+    // Type of the values loaded so far (of a sample of them in a long column).
+    pub fn column_type(&self) -> ColumnType {
+        self.types.column_type()
+    }
+    pub fn type_tally(&self) -> &TypeTally {
+        &self.types
+    }
+}
+
+trait RowSource {
+    fn read_row(&mut self, record: &mut csv::ByteRecord) -> csv::Result<bool>;
+    fn bytes_read(&self) -> u64;
+    fn can_seek(&self) -> bool {
+        false
+    }
+    fn seek(&mut self, _position: csv::Position) -> csv::Result<bool> {
+        Ok(false)
+    }
+}
+
+impl<R> RowSource for csv::Reader<R> where R: std::io::Read {
+    fn read_row(&mut self, record: &mut csv::ByteRecord) -> csv::Result<bool> {
+        self.read_byte_record(record)
+    }
+    fn bytes_read(&self) -> u64 {
+        self.position().byte()
+    }
+}
+
+struct SeekableSource<R>(csv::Reader<R>);
+
+impl<R> RowSource for SeekableSource<R> where R: std::io::Read + std::io::Seek {
+    fn read_row(&mut self, record: &mut csv::ByteRecord) -> csv::Result<bool> {
+        self.0.read_byte_record(record)
+    }
+    fn bytes_read(&self) -> u64 {
+        self.0.position().byte()
+    }
+    fn can_seek(&self) -> bool {
+        true
+    }
+    fn seek(&mut self, position: csv::Position) -> csv::Result<bool> {
+        self.0.seek(position).map(|_| true)
+    }
+}
+
+fn offset_of(position: Option<&csv::Position>) -> RowOffset {
+    position.map_or(RowOffset { byte: 0, line: 0 }, |position| RowOffset { byte: position.byte(), line: position.line() })
+}
+
+#[derive(Debug, Clone)]
+pub struct RowError {
+    pub row: usize,
+    pub line: u64,
+    pub byte: u64,
+    pub kind: String,
+    pub raw: Vec<String>, // Fields as read, with invalid UTF-8 replaced
+}
+
+impl RowError {
+    fn describe(error: &csv::Error) -> String {
+        match error.kind() {
+            csv::ErrorKind::UnequalLengths { expected_len, len, .. } => 
+                format!("expected {} fields, found {}", expected_len, len),
+            csv::ErrorKind::Utf8 { err, .. } => 
+                format!("invalid UTF-8 in field {}", err.field() + 1),
+            csv::ErrorKind::Io(error) => 
+                format!("I/O error: {}", error),
+            _ => error.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for RowError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "row {} (line {}, byte {}): {}: {:?}", self.row + 1, self.line, self.byte, self.kind, self.raw)
+    }
+}
+
+fn lossy_fields(record: &csv::ByteRecord) -> Vec<String> {
+    record.iter().map(|field| String::from_utf8_lossy(field).into_owned()).collect()
+}
+
+pub struct CSVLoader {
+    source: Box<dyn RowSource>,
+    record: csv::ByteRecord,
+    index: RowIndex,
+    next_row: usize, // Row the source will produce on the next read
+    header_records: u64,
+    bytes_total: Option<u64>,
+    row_total: Option<usize>,
+}
+
+impl std::fmt::Debug for CSVLoader {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("CSVLoader")
+            .field("bytes_read", &self.source.bytes_read())
+            .field("bytes_total", &self.bytes_total)
+            .field("next_row", &self.next_row)
+            .field("row_total", &self.row_total)
+            .field("index", &self.index.len())
+            .finish()
+    }
+}
+
+impl CSVLoader {
+    fn new(source: Box<dyn RowSource>, index: RowIndex, header_records: u64) -> Self {
+        let row_total = if index.is_complete() { Some(index.len()) } else { None };
+        CSVLoader { 
+            source, 
+            record: csv::ByteRecord::new(), 
+            index, 
+            next_row: 0, 
+            header_records, 
+            bytes_total: None, 
+            row_total,
+        }
+    }
+
+    fn reached_end(&mut self) {
+        self.row_total = Some(self.next_row);
+        if self.index.len() == self.next_row {
+            self.index.mark_complete();
+        }
+    }
+
+    fn record_offset(&mut self, offset: RowOffset) {
+        if self.index.len() == self.next_row {
+            self.index.push(offset);
+        }
+        self.next_row += 1;
+    }
+
+    // Seeks to the closest row the index has the offset of and reads on from there, unless reading
+    // on from where the source is already gets there sooner.
+    fn seek_to(&mut self, row_index: usize) -> bool {
+        if self.next_row == row_index {
+            return true;
+        }
+        let (checkpoint_row, offset) = match self.index.checkpoint(row_index) {
+            Some(checkpoint) => checkpoint,
+            None => return false,
+        };
+
+        if !(checkpoint_row <= self.next_row && self.next_row < row_index) {
+            let mut position = csv::Position::new();
+            position.set_byte(offset.byte);
+            position.set_line(offset.line);
+            position.set_record(checkpoint_row as u64 + self.header_records);
+
+            match self.source.seek(position) {
+                Ok(true) => self.next_row = checkpoint_row,
+                Ok(false) => return false,
+                Err(error) => {
+                    log::error!("Cannot seek to row {} in CSV file: {}", row_index, error);
+                    return false;
+                }
+            }
+        }
+
+        while self.next_row < row_index {
+            match self.source.read_row(&mut self.record) {
+                Ok(false) => return false,
+                Err(error) if matches!(error.kind(), csv::ErrorKind::Io(_)) => {
+                    log::error!("Error reading row {} in CSV file: {}", self.next_row, error);
+                    return false;
+                }
+                Ok(true) | Err(_) => {
+                    let offset = offset_of(self.record.position());
+                    self.record_offset(offset);
+                }
+            }
+        }
+        true
+    }
+
+    // Parses rows past the end of the index without keeping their values, just their offsets.
+    fn extend_index_to(&mut self, row_index: usize) {
+        if let Some(last) = self.index.len().checked_sub(1) {
+            if !self.seek_to(last) {
+                return;
+            }
+        }
+        while self.index.len() <= row_index && self.row_total.is_none() {
+            match self.source.read_row(&mut self.record) {
+                Ok(false) => self.reached_end(),
+                Err(error) if matches!(error.kind(), csv::ErrorKind::Io(_)) => {
+                    log::error!("Error reading row {} in CSV file: {}", self.next_row, error);
+                    self.reached_end();
+                }
+                // Malformed rows still take up a row, they are reported when they are loaded.
+                Ok(true) | Err(_) => {
+                    let offset = offset_of(self.record.position());
+                    self.record_offset(offset);
+                }
+            }
+        }
+    }
+}
+
+// Rows further than this past the loaded ones are reached by seeking rather than by reading
+// everything in between, if the source allows it.
+const SEEK_DISTANCE: usize = 1000;
+
+#[derive(Debug)]
+pub struct SaveError(PathBuf, String);
+impl std::fmt::Display for SaveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Cannot save CSV file {:?}: {}.", self.0, self.1)
+    }
 }
 
 #[derive(Debug)]
 pub struct CSVFile {
     columns: Vec<CSVColumn>,
+    first_loaded_row: usize,
+    last_loaded_row: usize, // Exclusive
+    loader: Option<CSVLoader>,
+    offsets: Vec<RowOffset>, // Where the loaded rows start in the file, while it is read from the file
+    errors: BTreeMap<usize, RowError>,
+    trim_fields: bool,
+    altered: bool, // Some values differ from the file: whitespace was trimmed or invalid UTF-8 replaced
 }
 
 impl CSVFile {
     pub fn new() -> Self {
-        CSVFile { columns: Vec::new() }
+        CSVFile { 
+            columns: Vec::new(), first_loaded_row: 0, last_loaded_row: 0, loader: None, offsets: Vec::new(), errors: BTreeMap::new(), 
+            trim_fields: false, altered: false,
+        }
     }
 
     pub fn new_column(&mut self, header: String) {        
@@ -221,7 +614,9 @@ impl CSVFile {
 
     fn get_column_mut(&mut self, column_index: usize) -> &mut CSVColumn {
         while column_index >= self.columns.len() {
-            self.columns.push(CSVColumn::default());            
+            let mut column = CSVColumn::default();
+            column.clear_values(self.first_loaded_row);
+            self.columns.push(column);
         } 
         &mut self.columns[column_index]
     }
@@ -230,36 +625,577 @@ impl CSVFile {
         self.columns.len()
     }
 
-    pub fn row_count(&mut self) -> usize {
-        // TODO: thius is inefficient for large column counts.
-        self.columns.iter().map(|column| column.row_count()).max().unwrap_or(0)        
+    // The header, or a stand-in for columns without one.
+    pub fn column_name(&self, column_index: usize) -> String {
+        match self.get_column(column_index).map(|column| column.header()) {
+            Some(header) if !header.is_empty() => header.to_owned(),
+            _ => format!("column {}", column_index + 1),
+        }
     }
-}
 
-impl<R> From<csv::Reader<R>> for CSVFile where R: std::io::Read {
-    fn from(mut reader: csv::Reader<R>) -> Self { 
+    // The first column with exactly this header, or else ignoring ASCII case.
+    pub fn column_named(&self, name: &str) -> Option<usize> {
+        self.columns.iter().position(|column| column.header() == name)
+            .or_else(|| self.columns.iter().position(|column| column.header().eq_ignore_ascii_case(name)))
+    }
 
-        let mut csv = CSVFile::new();
+    // Counts the rows known to exist so far, which is all of them if `total_row_count` is known.
+    pub fn row_count(&self) -> usize {
+        match &self.loader {
+            None => self.last_loaded_row,
+            Some(loader) => loader.row_total
+                .unwrap_or_else(|| std::cmp::max(self.last_loaded_row, loader.index.len())),
+        }
+    }
 
-        if reader.has_headers() {
-            let headers = reader.headers()
-                .expect("Error reading CSV file");      
-            for header in headers {
-                log::info!("header: {}", header);
-                csv.new_column(header.to_owned());
+    pub fn total_row_count(&self) -> Option<usize> {
+        match &self.loader {
+            None => Some(self.last_loaded_row),
+            Some(loader) => loader.row_total,
+        }
+    }
+
+    // Lets the loading progress be reported as a percentage, if the size of the input is known up front.
+    pub fn with_input_size(mut self, bytes_total: Option<u64>) -> Self {
+        if let Some(loader) = self.loader.as_mut() {
+            loader.bytes_total = bytes_total;
+        }
+        self
+    }
+
+    // Trims whitespace around headers and values, like the CSV reader would, but keeps track of
+    // whether that changed anything, since saving writes back the trimmed values.
+    pub fn with_trim(mut self, trim: csv::Trim) -> Self {
+        if trim == csv::Trim::Headers || trim == csv::Trim::All {
+            for column in self.columns.iter_mut() {
+                let header = column.header().trim_matches(|character: char| character.is_ascii_whitespace());
+                if header.len() != column.header().len() {
+                    column.set_header(header.to_owned());
+                    self.altered = true;
+                }
             }
         }
-            
-        for (row_index, row) in reader.records().into_iter().enumerate() {
-            let row = row.expect(&format!("Error reading row {} in CSV file", row_index));
-            for (column_index, value) in row.into_iter().enumerate() {
-                log::info!("item col:{}: {}", column_index, value);
-                let item = CSVItem::from(value);
-                let column = csv.get_column_mut(column_index);
+        self.trim_fields = trim == csv::Trim::Fields || trim == csv::Trim::All;
+        self
+    }
+
+    pub fn loaded_rows(&self) -> std::ops::Range<usize> {
+        self.first_loaded_row..self.last_loaded_row
+    }
+
+    pub fn is_fully_loaded(&self) -> bool {
+        self.total_row_count().is_some_and(|total| self.first_loaded_row == 0 && self.last_loaded_row >= total)
+    }
+
+    pub fn bytes_loaded(&self) -> Option<u64> {
+        self.loader.as_ref().map(|loader| loader.source.bytes_read())
+    }
+
+    pub fn bytes_total(&self) -> Option<u64> {
+        self.loader.as_ref().and_then(|loader| loader.bytes_total)
+    }
+
+    // Malformed rows found so far, in row order.
+    pub fn errors(&self) -> impl Iterator<Item=&RowError> {
+        self.errors.values()
+    }
+
+    pub fn error_count(&self) -> usize {
+        self.errors.len()
+    }
+
+    pub fn row_error(&self, row_index: usize) -> Option<&RowError> {
+        self.errors.get(&row_index)
+    }
+
+    // Known for the loaded rows and the last row indexed, as long as the rows are those in the file.
+    pub fn row_offset(&self, row_index: usize) -> Option<RowOffset> {
+        let loader = self.loader.as_ref()?;
+        row_index.checked_sub(self.first_loaded_row)
+            .and_then(|loaded| self.offsets.get(loaded).cloned())
+            .or_else(|| loader.index.last().filter(|_| row_index + 1 == loader.index.len()))
+    }
+
+    // Makes sure the values of the given rows are available, reading or seeking as needed. Rows
+    // beyond the end of the file are ignored.
+    pub fn ensure_loaded(&mut self, rows: std::ops::Range<usize>) {
+        let jump = rows.start < self.first_loaded_row || rows.start > self.last_loaded_row + SEEK_DISTANCE;
+        if jump {
+            self.jump_to(rows.start);
+        }
+        while self.last_loaded_row < rows.end && self.load_row() {}
+    }
+
+    // Reads ahead until the number of rows is known, only indexing the rows in between if the
+    // source can seek back to them later.
+    pub fn count_all_rows(&mut self) -> usize {
+        if self.total_row_count().is_none() {
+            match self.loader.as_mut() {
+                Some(loader) if loader.source.can_seek() => loader.extend_index_to(usize::MAX),
+                _ => self.load_all(),
+            }
+        }
+        self.row_count()
+    }
+
+    pub fn load_all(&mut self) {
+        while self.load_more(usize::MAX) {}
+    }
+
+    // Loads up to `rows` more rows towards loading all of them, so that a long load can be shown
+    // and interrupted. Returns whether there may be more.
+    pub fn load_more(&mut self, rows: usize) -> bool {
+        if self.first_loaded_row > 0 {
+            self.jump_to(0);
+        }
+        for _ in 0..rows {
+            if !self.load_row() {
+                return false;
+            }
+        }
+        true
+    }
+
+    // Drops the loaded rows and starts loading from `row_index` instead, if the source can seek.
+    fn jump_to(&mut self, row_index: usize) {
+        let loader = match self.loader.as_mut() {
+            Some(loader) => loader,
+            None => return,
+        };
+        if !loader.source.can_seek() {
+            return;
+        }
+
+        if loader.index.len() <= row_index {
+            loader.extend_index_to(row_index);
+        }
+        let row_index = std::cmp::min(row_index, loader.index.len().saturating_sub(1));
+        if !loader.seek_to(row_index) {
+            return;
+        }
+
+        log::info!("Jumping to row {}", row_index);
+        self.first_loaded_row = row_index;
+        self.last_loaded_row = row_index;
+        self.offsets.clear();
+        for column in self.columns.iter_mut() {
+            column.clear_values(row_index);
+        }
+    }
+
+    fn load_row(&mut self) -> bool {
+        let row_index = self.last_loaded_row;
+        let loader = match self.loader.as_mut() {
+            Some(loader) => loader,
+            None => return false,
+        };
+        if loader.row_total.is_some_and(|total| row_index >= total) {
+            return false;
+        }
+        if loader.next_row != row_index && !loader.seek_to(row_index) {
+            return false;
+        }
+
+        let result = loader.source.read_row(&mut loader.record);
+        let offset = offset_of(loader.record.position());
+
+        let error = match result {
+            Ok(false) => {
+                loader.reached_end();
+                return false;
+            }
+            Ok(true) => None,
+            Err(error) => {
+                log::warn!("Error reading row {} in CSV file: {}", row_index, error);
+                if let csv::ErrorKind::Io(_) = error.kind() {
+                    // Nothing more can be read, but whatever was loaded so far is still worth showing.
+                    loader.reached_end();
+                    self.errors.insert(row_index, RowError { 
+                        row: row_index, line: offset.line, byte: offset.byte, kind: RowError::describe(&error), raw: vec![],
+                    });
+                    return false;
+                }
+                Some(error)
+            }
+        };
+
+        loader.record_offset(offset);
+        self.offsets.push(offset);
+
+        if self.trim_fields {
+            let length = loader.record.as_slice().len();
+            loader.record.trim();
+            self.altered |= loader.record.as_slice().len() != length;
+        }
+
+        let record = std::mem::take(&mut loader.record);
+        let fields = match csv::StringRecord::from_byte_record(record) {
+            Ok(record) => {
+                let fields = record.iter().map(|field| field.to_owned()).collect();
+                if let Some(loader) = self.loader.as_mut() {
+                    loader.record = record.into_byte_record();
+                }
+                fields
+            }
+            Err(utf8_error) => {
+                let kind = format!("invalid UTF-8 in field {}", utf8_error.utf8_error().field() + 1);
+                let record = utf8_error.into_byte_record();
+                let fields = lossy_fields(&record);
+                let error = RowError { row: row_index, line: offset.line, byte: offset.byte, kind, raw: fields.clone() };
+                self.errors.insert(row_index, error);
+                self.altered = true;
+                if let Some(loader) = self.loader.as_mut() {
+                    loader.record = record;
+                }
+                fields
+            }
+        };
+
+        if let Some(error) = error {
+            self.errors.insert(row_index, RowError { 
+                row: row_index, line: offset.line, byte: offset.byte, kind: RowError::describe(&error), raw: fields.clone(),
+            });
+        }
+
+        self.push_row(fields);
+        true
+    }
+
+    // Adds a row after the loaded ones, as rows load, or to build a table in memory.
+    pub fn push_row(&mut self, fields: Vec<String>) {
+        let row_index = self.last_loaded_row;
+        for (column_index, value) in fields.into_iter().enumerate() {
+            log::info!("item col:{}: {}", column_index, value);
+            let item = CSVItem::from(value);
+            let column = self.get_column_mut(column_index);
+            column.set_value(row_index, item);
+        }
+        self.last_loaded_row += 1;
+    }
+
+    // Whether saving would also change values that were not edited, in the rows loaded so far.
+    pub fn is_altered(&self) -> bool {
+        self.altered
+    }
+
+    // Edits need every row in memory, and row indices stop matching the file once rows are inserted
+    // or deleted, so the rest of the file is read and the loader let go of. For a large file that
+    // is a lot of memory, and the whole file has to be read before the first edit.
+    fn detach_loader(&mut self) {
+        if self.loader.is_some() {
+            self.load_all();
+            self.loader = None;
+            self.offsets = Vec::new();
+            for column in self.columns.iter_mut() {
+                column.measure();
+            }
+        }
+    }
+
+    // Malformed rows are reported by row index, which moves as rows come and go.
+    fn shift_errors(&mut self, from_row: usize, inserted: bool) {
+        let errors = std::mem::take(&mut self.errors);
+        for (row_index, mut error) in errors {
+            let row_index = match row_index {
+                row_index if row_index < from_row => row_index,
+                row_index if inserted => row_index + 1,
+                row_index if row_index == from_row => continue,
+                row_index => row_index - 1,
+            };
+            error.row = row_index;
+            self.errors.insert(row_index, error);
+        }
+    }
+
+    // Returns the previous value, or `None` if it was missing. Setting a value past the end of a
+    // short row makes the row longer, with empty values in between.
+    pub fn set_value(&mut self, row_index: usize, column_index: usize, text: &str) -> Option<String> {
+        self.detach_loader();
+        let column = self.get_column_mut(column_index);
+        let previous = column.value(row_index).filter(|item| !item.is_missing()).map(|item| item.text());
+        column.set_value(row_index, CSVItem::from(text));
+        previous
+    }
+
+    // Makes a value missing again, so that a row made longer by `set_value` is as short as it was.
+    pub fn clear_value(&mut self, row_index: usize, column_index: usize) {
+        self.detach_loader();
+        let column = self.get_column_mut(column_index);
+        if column.value(row_index).is_some() {
+            column.set_value(row_index, CSVItem::default());
+        }
+    }
+
+    pub fn set_header(&mut self, column_index: usize, header: String) -> String {
+        self.detach_loader();
+        let column = self.get_column_mut(column_index);
+        let previous = column.header().to_owned();
+        column.set_header(header);
+        previous
+    }
+
+    // Inserts a row with the given fields before `row_index`. The row gets as many fields as given,
+    // so a row of empty strings is an empty row, and no fields at all is a row with missing values.
+    pub fn insert_row(&mut self, row_index: usize, fields: Vec<String>) {
+        self.detach_loader();
+        let row_index = std::cmp::min(row_index, self.last_loaded_row);
+        let column_count = std::cmp::max(self.columns.len(), fields.len());
+        let mut fields = fields.into_iter();
+        for column_index in 0..column_count {
+            let item = fields.next().map_or_else(CSVItem::default, CSVItem::from);
+            let column = self.get_column_mut(column_index);
+            if column.row_count() >= row_index {
+                column.insert_value(row_index, item);
+            } else if !item.is_missing() {
                 column.set_value(row_index, item);
-            }            
+            }
+        }
+        self.last_loaded_row += 1;
+        self.shift_errors(row_index, true);
+    }
+
+    // Returns the fields the row had.
+    pub fn delete_row(&mut self, row_index: usize) -> Vec<String> {
+        self.detach_loader();
+        if row_index >= self.last_loaded_row {
+            return Vec::new();
+        }
+        let mut fields: Vec<Option<CSVItem>> = self.columns.iter_mut().map(|column| column.remove_value(row_index)).collect();
+        while fields.last().is_some_and(|item| item.as_ref().map_or(true, |item| item.is_missing())) {
+            fields.pop();
+        }
+        self.last_loaded_row -= 1;
+        self.shift_errors(row_index, false);
+        fields.into_iter().map(|item| item.map_or_else(String::new, |item| item.text())).collect()
+    }
+
+    // Every row gets an empty value in the new column.
+    pub fn insert_column(&mut self, column_index: usize, header: String) {
+        self.detach_loader();
+        let mut column = CSVColumn::from_header(header);
+        for row_index in 0..self.last_loaded_row {
+            column.set_value(row_index, CSVItem::from(""));
+        }
+        let column_index = std::cmp::min(column_index, self.columns.len());
+        self.columns.insert(column_index, column);
+    }
+
+    pub fn delete_column(&mut self, column_index: usize) -> Option<CSVColumn> {
+        self.detach_loader();
+        if column_index >= self.columns.len() {
+            return None;
+        }
+        Some(self.columns.remove(column_index))
+    }
+
+    // Puts back a column taken out with `delete_column`.
+    pub fn restore_column(&mut self, column_index: usize, column: CSVColumn) {
+        self.detach_loader();
+        let column_index = std::cmp::min(column_index, self.columns.len());
+        self.columns.insert(column_index, column);
+    }
+
+    // Writes the headers (unless the file has none) and then each row with as many fields as it
+    // has, so that short rows stay short.
+    pub fn write<W: std::io::Write>(&self, writer: &mut csv::Writer<W>, headers: bool) -> csv::Result<()> {
+        if headers {
+            writer.write_record(self.columns.iter().map(|column| column.header()))?;
+        }
+        for row_index in 0..self.row_count() {
+            let items: Vec<Option<&CSVItem>> = self.columns.iter().map(|column| column.value(row_index)).collect();
+            let length = items.iter()
+                .rposition(|item| item.is_some_and(|item| !item.is_missing()))
+                .map_or(0, |last| last + 1);
+            writer.write_record(items[..length].iter().map(|item| item.map_or_else(String::new, |item| item.text())))?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    // Writes to a temporary file next to `path` first and then puts it in place, so that a failed
+    // save does not leave a truncated file behind.
+    pub fn save(&mut self, path: &Path, builder: &csv::WriterBuilder, headers: bool) -> Result<(), SaveError> {
+        self.load_all();
+
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(".hamstertmp");
+        let temporary = PathBuf::from(temporary);
+
+        let written = builder.from_path(&temporary).and_then(|mut writer| self.write(&mut writer, headers));
+        if let Err(error) = written {
+            let _ = std::fs::remove_file(&temporary);
+            return Err(SaveError(path.to_owned(), error.to_string()));
+        }
+        if let Ok(metadata) = std::fs::metadata(path) {
+            let _ = std::fs::set_permissions(&temporary, metadata.permissions());
+        }
+        if let Err(error) = std::fs::rename(&temporary, path) {
+            let _ = std::fs::remove_file(&temporary);
+            return Err(SaveError(path.to_owned(), error.to_string()));
+        }
+
+        // The cached row index would be rejected as out of date anyway.
+        let _ = std::fs::remove_file(RowIndex::cache_path(path));
+        Ok(())
+    }
+
+    fn with_loader<R>(mut reader: csv::Reader<R>, make_source: impl FnOnce(csv::Reader<R>) -> Box<dyn RowSource>, index: RowIndex) -> Self 
+        where R: std::io::Read {
+
+        let mut csv = CSVFile::new();
+
+        if reader.has_headers() {
+            // A broken header row should not stop us from showing the data, so decode it leniently.
+            match reader.byte_headers() {
+                Ok(headers) => {
+                    for header in lossy_fields(headers) {
+                        log::info!("header: {}", header);
+                        csv.new_column(header);
+                    }
+                }
+                Err(error) => log::error!("Error reading CSV headers: {}", error),
+            }
         }
 
+        // Rows are only read when the display asks for them.
+        let header_records = if reader.has_headers() { 1 } else { 0 };
+        csv.loader = Some(CSVLoader::new(make_source(reader), index, header_records));
         csv
     }
-}
\ No newline at end of file
+
+    // Like `CSVFile::from`, but can jump straight to any row listed in `index`, and extends the
+    // index as rows are read.
+    pub fn indexed<R>(reader: csv::Reader<R>, index: RowIndex) -> Self where R: std::io::Read + std::io::Seek + 'static {
+        CSVFile::with_loader(reader, |reader| Box::new(SeekableSource(reader)), index)
+    }
+}
+
+impl<R> From<csv::Reader<R>> for CSVFile where R: std::io::Read + 'static {
+    fn from(reader: csv::Reader<R>) -> Self { 
+        CSVFile::with_loader(reader, |reader| Box::new(reader), RowIndex::default())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // A file read from `text`, with no rows loaded yet.
+    pub(crate) fn unloaded(text: &str) -> CSVFile {
+        CSVFile::from(csv::Reader::from_reader(std::io::Cursor::new(text.as_bytes().to_vec())))
+    }
+
+    // A file read from `text`, with every row loaded.
+    pub(crate) fn csv(text: &str) -> CSVFile {
+        let mut csv = unloaded(text);
+        csv.load_all();
+        csv
+    }
+
+    fn cut(text: &str, width: usize) -> Vec<String> {
+        let text = text.to_owned();
+        let line = text.cut_or_pad_to(width, PADDING);
+        assert_eq!(display_width(&line.join("")), width);
+        line.into_iter().map(|grapheme| grapheme.to_owned()).collect()
+    }
+
+    #[test]
+    fn graphemes_take_up_to_two_cells() {
+        assert_eq!(grapheme_width("a"), 1);
+        assert_eq!(grapheme_width("漢"), 2);
+        assert_eq!(grapheme_width("e\u{301}"), 1);
+        assert_eq!(grapheme_width("\u{301}"), 0);
+        assert_eq!(grapheme_width("👨\u{200d}👩\u{200d}👧"), 2);
+        assert_eq!(display_width("漢字 e\u{301}👨\u{200d}👩\u{200d}👧"), 8);
+    }
+
+    #[test]
+    fn wide_graphemes_that_do_not_fit_leave_a_gap() {
+        assert_eq!(cut("漢字", 3), vec!["漢", ELIPSIS]);
+        assert_eq!(cut("漢字", 5), vec!["漢", "字", PADDING]);
+        assert_eq!(cut("漢", 3), vec!["漢", PADDING]);
+        assert_eq!(cut("漢字テ", 5), vec!["漢", "字", ELIPSIS]);
+        assert_eq!(cut("a漢", 2), vec!["a", ELIPSIS]);
+    }
+
+    #[test]
+    fn combining_marks_stay_with_their_letter() {
+        assert_eq!(cut("e\u{301}e\u{301}", 2), vec!["e\u{301}", "e\u{301}"]);
+        assert_eq!(cut("e\u{301}e\u{301}x", 2), vec!["e\u{301}", ELIPSIS]);
+    }
+
+    #[test]
+    fn markers_replace_a_wide_last_grapheme_and_keep_the_width() {
+        assert_eq!(cut("ab漢字", 4), vec!["a", "b", ELIPSIS, PADDING]);
+        assert_eq!(cut("👨\u{200d}👩\u{200d}👧👨\u{200d}👩\u{200d}👧x", 4), vec!["👨\u{200d}👩\u{200d}👧", ELIPSIS, PADDING]);
+
+        let item = CSVItem::from("漢字\nx");
+        assert_eq!((item.width(), item.height()), (4, 2));
+        let lines = item.cut_or_pad_to(CellDimentions { width: 4, height: 1 }, PADDING);
+        assert_eq!(lines, vec![vec!["漢", PAGE, PADDING]]);
+
+        let lines = item.cut_or_pad_to(CellDimentions { width: 3, height: 1 }, PADDING);
+        assert_eq!(lines, vec![vec!["漢", PAGE]]);
+
+        let lines = item.cut_or_pad_to(CellDimentions { width: 2, height: 3 }, PADDING);
+        assert_eq!(lines, vec![vec![ELIPSIS, PADDING], vec!["x", PADDING], vec![PADDING, PADDING]]);
+    }
+
+    fn column(values: &[&str]) -> CSVColumn {
+        let mut column = CSVColumn::from_header("h".to_owned());
+        for (index, value) in values.iter().enumerate() {
+            column.set_value(index, CSVItem::from(*value));
+        }
+        column
+    }
+
+    #[test]
+    fn sizes_shrink_once_the_widest_value_is_gone() {
+        let mut column = column(&["abc", "abc", "a\nb"]);
+        assert_eq!((column.max_width(), column.max_height()), (3, 2));
+        column.remove_value(0);
+        assert_eq!((column.max_width(), column.max_height()), (3, 2));
+        column.set_value(0, CSVItem::from("ab"));
+        assert_eq!((column.max_width(), column.max_height()), (2, 2));
+        column.remove_value(1);
+        assert_eq!((column.max_width(), column.max_height()), (2, 1));
+        column.set_header("header".to_owned());
+        assert_eq!(column.max_width(), 6);
+        column.set_header("h".to_owned());
+        assert_eq!(column.max_width(), 2);
+    }
+
+    #[test]
+    fn sizes_grow_with_inserted_values() {
+        let mut column = column(&["a"]);
+        column.insert_value(0, CSVItem::from("abcd\ne\nf"));
+        assert_eq!((column.max_width(), column.max_height()), (4, 3));
+        column.insert_value(4, CSVItem::from("abcde"));
+        assert_eq!((column.row_count(), column.max_width()), (5, 5));
+        assert!(column.value(2).unwrap().is_missing());
+    }
+
+    #[test]
+    fn types_follow_edits() {
+        let mut column = column(&["1", "2", "x"]);
+        assert_eq!(column.column_type(), ColumnType::Mixed);
+        column.set_value(2, CSVItem::from("3.5"));
+        assert_eq!(column.column_type(), ColumnType::Float);
+        column.remove_value(2);
+        assert_eq!(column.column_type(), ColumnType::Integer);
+        column.insert_value(0, CSVItem::from("yes"));
+        assert_eq!(column.column_type(), ColumnType::Mixed);
+    }
+
+    #[test]
+    fn types_are_sampled_from_the_first_values() {
+        let mut values = vec!["1"; TYPE_SAMPLE_SIZE];
+        values.push("x");
+        let mut column = column(&values);
+        assert_eq!(column.column_type(), ColumnType::Integer);
+        column.remove_value(0);
+        assert_eq!(column.column_type(), ColumnType::Mixed);
+        column.insert_value(0, CSVItem::from("1"));
+        assert_eq!(column.column_type(), ColumnType::Integer);
+    }
+}
@@ -0,0 +1,276 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::csv::{display_width, grapheme_width};
+
+const INDENT: &str = "  ";
+
+// Breaks each line of `text` at spaces so that no line is wider than `width`. Words that do not
+// fit on a line of their own are broken up anywhere. Indentation that leaves no room is dropped.
+pub fn wrap(text: &str, width: usize) -> Vec<String> {
+    let width = std::cmp::max(width, 1);
+    let mut lines = Vec::new();
+    for paragraph in text.lines() {
+        let mut indentation: String = paragraph.chars().take_while(|character| character.is_whitespace()).collect();
+        if display_width(&indentation) >= width {
+            indentation.clear();
+        }
+        let mut line = indentation.clone();
+        let mut line_width = display_width(&line);
+        let mut empty = true;
+
+        for word in paragraph.split_whitespace() {
+            let word_width = display_width(word);
+            let separator = if empty { 0 } else { 1 };
+            if line_width + separator + word_width <= width {
+                if !empty {
+                    line.push(' ');
+                }
+                line.push_str(word);
+                line_width += separator + word_width;
+                empty = false;
+                continue;
+            }
+            if !empty {
+                lines.push(std::mem::replace(&mut line, indentation.clone()));
+                line_width = display_width(&line);
+            }
+            for grapheme in word.graphemes(true) {
+                let grapheme_width = grapheme_width(grapheme);
+                if line_width + grapheme_width > width && line_width > 0 {
+                    lines.push(std::mem::take(&mut line));
+                    line_width = 0;
+                }
+                line.push_str(grapheme);
+                line_width += grapheme_width;
+            }
+            empty = false;
+        }
+        lines.push(line);
+    }
+    if lines.is_empty() {
+        lines.push(String::new());
+    }
+    lines
+}
+
+// Re-indents JSON objects and arrays, one member per line. Returns nothing if the text does not
+// look like JSON. Scalars are left as they are, so this does not validate them.
+pub fn pretty_json(text: &str) -> Option<String> {
+    let text = text.trim();
+    let looks_like_json = (text.starts_with('{') && text.ends_with('}')) || (text.starts_with('[') && text.ends_with(']'));
+    if !looks_like_json {
+        return None;
+    }
+
+    let mut output = String::new();
+    let mut depth: usize = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut characters = text.chars().peekable();
+    let newline = |output: &mut String, depth: usize| {
+        output.push('\n');
+        output.push_str(&INDENT.repeat(depth));
+    };
+
+    while let Some(character) = characters.next() {
+        if in_string {
+            output.push(character);
+            match character {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => (),
+            }
+            continue;
+        }
+        match character {
+            '"' => { in_string = true; output.push(character); }
+            '{' | '[' => {
+                output.push(character);
+                let closing = if character == '{' { '}' } else { ']' };
+                while characters.peek().is_some_and(|character| character.is_whitespace()) {
+                    characters.next();
+                }
+                if characters.peek() == Some(&closing) {
+                    output.push(closing);
+                    characters.next();
+                } else {
+                    depth += 1;
+                    newline(&mut output, depth);
+                }
+            }
+            '}' | ']' => {
+                depth = depth.checked_sub(1)?;
+                newline(&mut output, depth);
+                output.push(character);
+            }
+            ',' => { output.push(character); newline(&mut output, depth); }
+            ':' => output.push_str(": "),
+            character if character.is_whitespace() => (),
+            character => output.push(character),
+        }
+    }
+
+    if depth != 0 || in_string {
+        return None;
+    }
+    Some(output)
+}
+
+enum XmlToken<'a> {
+    Open(&'a str),
+    Close(&'a str),
+    Single(&'a str), // Self-closing tags, comments, declarations
+    Text(&'a str),
+}
+
+// Puts each XML element on a line of its own, indented by depth. Elements that only contain text
+// stay on one line. Returns nothing if the text does not look like XML, or its tags do not balance.
+pub fn pretty_xml(text: &str) -> Option<String> {
+    let text = text.trim();
+    if !(text.starts_with('<') && text.ends_with('>')) {
+        return None;
+    }
+
+    let mut tokens = Vec::new();
+    let mut rest = text;
+    while !rest.is_empty() {
+        if rest.starts_with('<') {
+            let end = if rest.starts_with("<!--") { rest.find("-->")? + 3 } else { rest.find('>')? + 1 };
+            let tag = &rest[..end];
+            tokens.push(if tag.starts_with("</") {
+                XmlToken::Close(tag)
+            } else if tag.ends_with("/>") || tag.starts_with("<?") || tag.starts_with("<!") {
+                XmlToken::Single(tag)
+            } else {
+                XmlToken::Open(tag)
+            });
+            rest = &rest[end..];
+        } else {
+            let end = rest.find('<').unwrap_or(rest.len());
+            let content = rest[..end].trim();
+            if !content.is_empty() {
+                tokens.push(XmlToken::Text(content));
+            }
+            rest = &rest[end..];
+        }
+    }
+
+    let mut lines = Vec::new();
+    let mut depth: usize = 0;
+    let mut position = 0;
+    while position < tokens.len() {
+        let indent = INDENT.repeat(depth);
+        match (&tokens[position], tokens.get(position + 1), tokens.get(position + 2)) {
+            (XmlToken::Open(open), Some(XmlToken::Text(content)), Some(XmlToken::Close(close))) => {
+                lines.push(format!("{}{}{}{}", indent, open, content, close));
+                position += 3;
+                continue;
+            }
+            (XmlToken::Open(open), Some(XmlToken::Close(close)), _) => {
+                lines.push(format!("{}{}{}", indent, open, close));
+                position += 2;
+                continue;
+            }
+            (XmlToken::Open(open), _, _) => {
+                lines.push(format!("{}{}", indent, open));
+                depth += 1;
+            }
+            (XmlToken::Close(close), _, _) => {
+                depth = depth.checked_sub(1)?;
+                lines.push(format!("{}{}", INDENT.repeat(depth), close));
+            }
+            (XmlToken::Single(tag), _, _) => lines.push(format!("{}{}", indent, tag)),
+            (XmlToken::Text(content), _, _) => lines.push(format!("{}{}", indent, content)),
+        }
+        position += 1;
+    }
+    if depth != 0 {
+        return None;
+    }
+    Some(lines.join("\n"))
+}
+
+pub fn pretty(text: &str) -> Option<String> {
+    pretty_json(text).or_else(|| pretty_xml(text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wraps_at_spaces() {
+        assert_eq!(wrap("", 10), vec![""]);
+        assert_eq!(wrap("the quick brown fox", 10), vec!["the quick", "brown fox"]);
+        assert_eq!(wrap("a\n\nb", 5), vec!["a", "", "b"]);
+        assert_eq!(wrap("漢字 漢字", 5), vec!["漢字", "漢字"]);
+    }
+
+    #[test]
+    fn breaks_up_long_words() {
+        assert_eq!(wrap("abcdefgh", 3), vec!["abc", "def", "gh"]);
+        assert_eq!(wrap("a abcdefgh", 4), vec!["a", "abcd", "efgh"]);
+        assert_eq!(wrap("漢字漢", 3), vec!["漢", "字", "漢"]);
+    }
+
+    #[test]
+    fn keeps_indentation_if_there_is_room() {
+        assert_eq!(wrap("  one two three", 9), vec!["  one two", "  three"]);
+        assert_eq!(wrap("        word", 4), vec!["word"]);
+        assert_eq!(wrap("    word", 6), vec!["    wo", "rd"]);
+        assert_eq!(wrap("   ", 2), vec![""]);
+    }
+
+    #[test]
+    fn pretty_prints_json() {
+        assert_eq!(pretty_json("[1,[2, 3]]").unwrap(), "[\n  1,\n  [\n    2,\n    3\n  ]\n]");
+        assert_eq!(pretty_json(r#" {"a":1,"b":{"c":null}} "#).unwrap(), "{\n  \"a\": 1,\n  \"b\": {\n    \"c\": null\n  }\n}");
+    }
+
+    #[test]
+    fn keeps_empty_json_containers_on_one_line() {
+        assert_eq!(pretty_json("{}").unwrap(), "{}");
+        assert_eq!(pretty_json("[ ]").unwrap(), "[]");
+        assert_eq!(pretty_json(r#"{"a": {}, "b": [ ]}"#).unwrap(), "{\n  \"a\": {},\n  \"b\": []\n}");
+    }
+
+    #[test]
+    fn leaves_json_strings_alone() {
+        assert_eq!(pretty_json(r#"{"a":"x\"}{, y:\\"}"#).unwrap(), "{\n  \"a\": \"x\\\"}{, y:\\\\\"\n}");
+    }
+
+    #[test]
+    fn rejects_what_is_not_json() {
+        assert_eq!(pretty_json("plain text"), None);
+        assert_eq!(pretty_json("{\"a\": [1}"), None);
+        assert_eq!(pretty_json("[1]]"), None);
+        assert_eq!(pretty_json("{\"a\": \"}"), None);
+    }
+
+    #[test]
+    fn pretty_prints_xml() {
+        assert_eq!(pretty_xml("<a>hi</a>").unwrap(), "<a>hi</a>");
+        assert_eq!(
+            pretty_xml(r#"<?xml version="1.0"?><a> <b>x</b><c/><d></d><!-- <e> --></a>"#).unwrap(),
+            "<?xml version=\"1.0\"?>\n<a>\n  <b>x</b>\n  <c/>\n  <d></d>\n  <!-- <e> -->\n</a>",
+        );
+        assert_eq!(pretty_xml("<a>x<b>y</b></a>").unwrap(), "<a>\n  x\n  <b>y</b>\n</a>");
+    }
+
+    #[test]
+    fn rejects_what_is_not_xml() {
+        assert_eq!(pretty_xml("plain text"), None);
+        assert_eq!(pretty_xml("<a><b></a>"), None);
+        assert_eq!(pretty_xml("</a><a>"), None);
+        assert_eq!(pretty_xml("<a>x<b</a>"), None);
+        assert_eq!(pretty_xml("<a><!-- x</a>"), None);
+    }
+
+    #[test]
+    fn tries_json_before_xml() {
+        assert_eq!(pretty("[]").unwrap(), "[]");
+        assert_eq!(pretty("<a></a>").unwrap(), "<a></a>");
+        assert_eq!(pretty("text"), None);
+    }
+}
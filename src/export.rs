@@ -0,0 +1,378 @@
+use std::fmt::{Display, Formatter};
+use std::io::Write;
+use std::str::FromStr;
+
+use regex::Regex;
+
+use crate::cli::Dialect;
+use crate::csv::CSVFile;
+use crate::types::{parse_boolean, ColumnType};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Csv,
+    Tsv,
+    Json,
+    Ndjson,
+    Markdown,
+    Html,
+    Latex,
+}
+
+impl Format {
+    pub const ALL: [Format; 7] = [
+        Format::Csv, Format::Tsv, Format::Json, Format::Ndjson, Format::Markdown, Format::Html, Format::Latex,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Format::Csv => "csv",
+            Format::Tsv => "tsv",
+            Format::Json => "json",
+            Format::Ndjson => "ndjson",
+            Format::Markdown => "markdown",
+            Format::Html => "html",
+            Format::Latex => "latex",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Format::Csv => "CSV",
+            Format::Tsv => "TSV",
+            Format::Json => "JSON, an array of objects",
+            Format::Ndjson => "NDJSON, one object per line",
+            Format::Markdown => "Markdown table",
+            Format::Html => "HTML table",
+            Format::Latex => "LaTeX tabular",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Markdown => "md",
+            Format::Latex => "tex",
+            format => format.name(),
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = FormatParseError;
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        match string.to_lowercase().as_str() {
+            "md" => Ok(Format::Markdown),
+            "tex" => Ok(Format::Latex),
+            other => Format::ALL.iter().find(|format| format.name() == other).cloned()
+                .ok_or_else(|| FormatParseError(string.to_owned())),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct FormatParseError(String);
+impl Display for FormatParseError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), std::fmt::Error> {
+        write!(f, "Invalid export format \"{}\": expected \"csv\", \"tsv\", \"json\", \"ndjson\", \"markdown\", \"html\", or \"latex\".", self.0)
+    }
+}
+
+// Some rows of some columns of a file, in the order given. Rows that are shorter than others have
+// empty values in the missing columns.
+pub struct Table<'a> {
+    pub csv: &'a CSVFile,
+    pub rows: Vec<usize>,
+    pub columns: Vec<usize>,
+    pub headers: bool, // CSV and TSV leave them out if the file has none, other formats name the columns anyway
+}
+
+impl<'a> Table<'a> {
+    fn text(&self, row_index: usize, column_index: usize) -> String {
+        self.csv.get_column(column_index)
+            .and_then(|column| column.value(row_index))
+            .map_or_else(String::new, |item| item.text())
+    }
+
+    fn names(&self) -> Vec<String> {
+        self.columns.iter().map(|&column_index| self.csv.column_name(column_index)).collect()
+    }
+
+    fn column_types(&self) -> Vec<ColumnType> {
+        self.columns.iter()
+            .map(|&column_index| self.csv.get_column(column_index).map_or(ColumnType::Empty, |column| column.column_type()))
+            .collect()
+    }
+
+    pub fn write(&self, format: Format, dialect: &Dialect, output: &mut dyn Write) -> std::io::Result<()> {
+        match format {
+            Format::Csv => self.write_csv(dialect, output),
+            Format::Tsv => self.write_csv(&Dialect { delimiter: b'\t', ..dialect.clone() }, output),
+            Format::Json => self.write_json(false, output),
+            Format::Ndjson => self.write_json(true, output),
+            Format::Markdown => self.write_markdown(output),
+            Format::Html => self.write_html(output),
+            Format::Latex => self.write_latex(output),
+        }
+    }
+
+    fn write_csv(&self, dialect: &Dialect, output: &mut dyn Write) -> std::io::Result<()> {
+        let mut writer = dialect.build_writer().from_writer(output);
+        if self.headers {
+            let headers = self.columns.iter()
+                .map(|&column_index| self.csv.get_column(column_index).map_or("", |column| column.header()));
+            writer.write_record(headers)?;
+        }
+        for &row_index in self.rows.iter() {
+            writer.write_record(self.columns.iter().map(|&column_index| self.text(row_index, column_index)))?;
+        }
+        writer.flush()
+    }
+
+    // Values of numeric and boolean columns are written as numbers and booleans where they can be,
+    // and as null where they are empty. Everything else is a string.
+    fn write_json(&self, one_per_line: bool, output: &mut dyn Write) -> std::io::Result<()> {
+        let number = Regex::new(r"^-?(0|[1-9][0-9]*)(\.[0-9]+)?([eE][+-]?[0-9]+)?$").unwrap();
+        let names: Vec<String> = self.names().iter().map(|name| json_string(name)).collect();
+        let types = self.column_types();
+        let json_value = |text: &str, column_type: ColumnType| -> String {
+            let trimmed = text.trim();
+            match column_type {
+                _ if trimmed.is_empty() && column_type != ColumnType::String && column_type != ColumnType::Mixed => "null".to_owned(),
+                ColumnType::Integer | ColumnType::Float if number.is_match(trimmed) => trimmed.to_owned(),
+                ColumnType::Boolean => parse_boolean(trimmed).map_or_else(|| json_string(text), |boolean| boolean.to_string()),
+                _ => json_string(text),
+            }
+        };
+
+        if !one_per_line {
+            write!(output, "[")?;
+        }
+        for (position, &row_index) in self.rows.iter().enumerate() {
+            let members: Vec<String> = self.columns.iter().enumerate()
+                .map(|(index, &column_index)| format!("{}: {}", names[index], json_value(&self.text(row_index, column_index), types[index])))
+                .collect();
+            let object = format!("{{{}}}", members.join(", "));
+            if one_per_line {
+                writeln!(output, "{}", object)?;
+            } else {
+                let separator = if position == 0 { "" } else { "," };
+                write!(output, "{}\n  {}", separator, object)?;
+            }
+        }
+        if !one_per_line {
+            writeln!(output, "\n]")?;
+        }
+        Ok(())
+    }
+
+    // Numeric columns are aligned to the right.
+    fn write_markdown(&self, output: &mut dyn Write) -> std::io::Result<()> {
+        let cell = |text: &str| text.replace('\\', "\\\\").replace('|', "\\|").replace('\n', "<br>");
+        let names: Vec<String> = self.names().iter().map(|name| cell(name)).collect();
+        let rules: Vec<&str> = self.column_types().iter()
+            .map(|column_type| if column_type.is_numeric() { "---:" } else { "---" })
+            .collect();
+
+        writeln!(output, "| {} |", names.join(" | "))?;
+        writeln!(output, "| {} |", rules.join(" | "))?;
+        for &row_index in self.rows.iter() {
+            let values: Vec<String> = self.columns.iter().map(|&column_index| cell(&self.text(row_index, column_index))).collect();
+            writeln!(output, "| {} |", values.join(" | "))?;
+        }
+        Ok(())
+    }
+
+    fn write_html(&self, output: &mut dyn Write) -> std::io::Result<()> {
+        let cell = |text: &str| text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;").replace('\n', "<br>");
+
+        writeln!(output, "<table>")?;
+        writeln!(output, "  <thead>")?;
+        let names: Vec<String> = self.names().iter().map(|name| format!("<th>{}</th>", cell(name))).collect();
+        writeln!(output, "    <tr>{}</tr>", names.join(""))?;
+        writeln!(output, "  </thead>")?;
+        writeln!(output, "  <tbody>")?;
+        for &row_index in self.rows.iter() {
+            let values: Vec<String> = self.columns.iter()
+                .map(|&column_index| format!("<td>{}</td>", cell(&self.text(row_index, column_index))))
+                .collect();
+            writeln!(output, "    <tr>{}</tr>", values.join(""))?;
+        }
+        writeln!(output, "  </tbody>")?;
+        writeln!(output, "</table>")
+    }
+
+    // Numeric columns are aligned to the right. Values that span lines are joined with spaces.
+    fn write_latex(&self, output: &mut dyn Write) -> std::io::Result<()> {
+        let cell = |text: &str| {
+            let mut escaped = String::new();
+            for character in text.chars() {
+                match character {
+                    '\\' => escaped.push_str("\\textbackslash{}"),
+                    '~' => escaped.push_str("\\textasciitilde{}"),
+                    '^' => escaped.push_str("\\textasciicircum{}"),
+                    '&' | '%' | '$' | '#' | '_' | '{' | '}' => { escaped.push('\\'); escaped.push(character); }
+                    '\n' | '\r' => escaped.push(' '),
+                    character => escaped.push(character),
+                }
+            }
+            escaped
+        };
+        let alignment: String = self.column_types().iter()
+            .map(|column_type| if column_type.is_numeric() { 'r' } else { 'l' })
+            .collect();
+        let names: Vec<String> = self.names().iter().map(|name| cell(name)).collect();
+
+        writeln!(output, "\\begin{{tabular}}{{{}}}", alignment)?;
+        writeln!(output, "\\hline")?;
+        writeln!(output, "{} \\\\", names.join(" & "))?;
+        writeln!(output, "\\hline")?;
+        for &row_index in self.rows.iter() {
+            let values: Vec<String> = self.columns.iter().map(|&column_index| cell(&self.text(row_index, column_index))).collect();
+            writeln!(output, "{} \\\\", values.join(" & "))?;
+        }
+        writeln!(output, "\\hline")?;
+        writeln!(output, "\\end{{tabular}}")
+    }
+}
+
+fn json_string(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for character in text.chars() {
+        match character {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            character if character.is_control() => quoted.push_str(&format!("\\u{:04x}", character as u32)),
+            character => quoted.push(character),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::csv::tests::csv;
+
+    const TEXT: &str = r#"name,id,ok,note
+a|b,007,yes,"say ""hi"" & <go>"
+x_1\y,12,,50% {off} ~ ^ $ #
+c,,no,"line1
+line2"
+"#;
+
+    fn export(csv: &CSVFile, rows: Vec<usize>, columns: Vec<usize>, format: Format) -> String {
+        export_with(csv, rows, columns, format, &Dialect::default(), true)
+    }
+
+    fn export_with(csv: &CSVFile, rows: Vec<usize>, columns: Vec<usize>, format: Format, dialect: &Dialect, headers: bool) -> String {
+        let table = Table { csv, rows, columns, headers };
+        let mut output = Vec::new();
+        table.write(format, dialect, &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    fn export_all(format: Format) -> String {
+        let csv = csv(TEXT);
+        export(&csv, (0..csv.row_count()).collect(), (0..csv.column_count()).collect(), format)
+    }
+
+    #[test]
+    fn csv_quotes_only_what_it_has_to() {
+        assert_eq!(export_all(Format::Csv), concat!(
+            "name,id,ok,note\n",
+            "a|b,007,yes,\"say \"\"hi\"\" & <go>\"\n",
+            "x_1\\y,12,,50% {off} ~ ^ $ #\n",
+            "c,,no,\"line1\nline2\"\n",
+        ));
+    }
+
+    #[test]
+    fn csv_follows_the_dialect() {
+        let csv = csv("a,b,c\n1;2,x'y\n3\n");
+        let dialect = Dialect { delimiter: b';', quote: b'\'', terminator: ::csv::Terminator::CRLF, ..Dialect::default() };
+        assert_eq!(export_with(&csv, vec![0, 1], vec![0, 1, 2], Format::Csv, &dialect, true), "a;b;c\r\n'1;2';'x''y';\r\n3;;\r\n");
+
+        let dialect = Dialect { quoting: false, ..Dialect::default() };
+        assert_eq!(export_with(&csv, vec![0], vec![1, 0], Format::Csv, &dialect, false), "x'y,1;2\n");
+    }
+
+    #[test]
+    fn tsv_uses_tabs_whatever_the_delimiter() {
+        let csv = csv("a,b\n\"x\ty\",z\n1\n");
+        let dialect = Dialect { delimiter: b';', ..Dialect::default() };
+        assert_eq!(export_with(&csv, vec![0, 1], vec![0, 1], Format::Tsv, &dialect, true), "a\tb\n\"x\ty\"\tz\n1\t\n");
+        assert_eq!(export_with(&csv, vec![1], vec![0, 1], Format::Tsv, &dialect, false), "1\t\n");
+    }
+
+    #[test]
+    fn json() {
+        assert_eq!(export_all(Format::Json), concat!(
+            "[\n",
+            "  {\"name\": \"a|b\", \"id\": \"007\", \"ok\": true, \"note\": \"say \\\"hi\\\" & <go>\"},\n",
+            "  {\"name\": \"x_1\\\\y\", \"id\": 12, \"ok\": null, \"note\": \"50% {off} ~ ^ $ #\"},\n",
+            "  {\"name\": \"c\", \"id\": null, \"ok\": false, \"note\": \"line1\\nline2\"}\n",
+            "]\n",
+        ));
+    }
+
+    #[test]
+    fn ndjson() {
+        let csv = csv("name,count\n\"tab\there\",-1.5e3\n\u{1},\n");
+        assert_eq!(export(&csv, vec![1, 0], vec![1, 0], Format::Ndjson), concat!(
+            "{\"count\": null, \"name\": \"\\u0001\"}\n",
+            "{\"count\": -1.5e3, \"name\": \"tab\\there\"}\n",
+        ));
+    }
+
+    #[test]
+    fn json_names_columns_without_headers() {
+        let csv = csv("a,\n1,2\n");
+        assert_eq!(export(&csv, vec![0], vec![0, 1], Format::Ndjson), "{\"a\": 1, \"column 2\": 2}\n");
+    }
+
+    #[test]
+    fn markdown() {
+        assert_eq!(export_all(Format::Markdown), concat!(
+            "| name | id | ok | note |\n",
+            "| --- | ---: | --- | --- |\n",
+            "| a\\|b | 007 | yes | say \"hi\" & <go> |\n",
+            "| x_1\\\\y | 12 |  | 50% {off} ~ ^ $ # |\n",
+            "| c |  | no | line1<br>line2 |\n",
+        ));
+    }
+
+    #[test]
+    fn html() {
+        assert_eq!(export_all(Format::Html), concat!(
+            "<table>\n",
+            "  <thead>\n",
+            "    <tr><th>name</th><th>id</th><th>ok</th><th>note</th></tr>\n",
+            "  </thead>\n",
+            "  <tbody>\n",
+            "    <tr><td>a|b</td><td>007</td><td>yes</td><td>say &quot;hi&quot; &amp; &lt;go&gt;</td></tr>\n",
+            "    <tr><td>x_1\\y</td><td>12</td><td></td><td>50% {off} ~ ^ $ #</td></tr>\n",
+            "    <tr><td>c</td><td></td><td>no</td><td>line1<br>line2</td></tr>\n",
+            "  </tbody>\n",
+            "</table>\n",
+        ));
+    }
+
+    #[test]
+    fn latex() {
+        assert_eq!(export_all(Format::Latex), concat!(
+            "\\begin{tabular}{lrll}\n",
+            "\\hline\n",
+            "name & id & ok & note \\\\\n",
+            "\\hline\n",
+            "a|b & 007 & yes & say \"hi\" \\& <go> \\\\\n",
+            "x\\_1\\textbackslash{}y & 12 &  & 50\\% \\{off\\} \\textasciitilde{} \\textasciicircum{} \\$ \\# \\\\\n",
+            "c &  & no & line1 line2 \\\\\n",
+            "\\hline\n",
+            "\\end{tabular}\n",
+        ));
+    }
+}
@@ -0,0 +1,515 @@
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter};
+
+use regex::Regex;
+
+use crate::csv::CSVFile;
+use crate::types::{parse_boolean, parse_date, parse_number, ColumnType};
+
+// Filter expressions select rows by their values, for example:
+//
+//     status != 200 && latency_ms > 500
+//     `user agent` contains "curl" or not (#3 =~ "^2021-0[1-6]")
+//
+// Columns are referred to by header (in backticks if the header is not a plain word) or by their
+// 1-based index after `#`. Bare words that are not headers are taken to be text, but only on the
+// right of a comparison, so that a misspelled column is an error rather than a filter that never
+// matches. Every comparison needs a column on at least one side.
+
+#[derive(Debug)]
+pub struct FilterError(String);
+impl Display for FilterError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), std::fmt::Error> {
+        write!(f, "Invalid filter: {}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    LeftParenthesis,
+    RightParenthesis,
+    And,
+    Or,
+    Not,
+    Operator(Operator),
+    Text(String),   // Quoted
+    Word(String),   // Bare, either a header or text
+    Header(String), // In backticks
+    Index(usize),   // `#n`, 1-based
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Contains,
+    StartsWith,
+    EndsWith,
+    Matches,
+    DoesNotMatch,
+}
+
+// Consumes the current character, and the next one too if it is `expected`.
+fn next_is(expected: char, characters: &mut std::iter::Peekable<std::str::Chars>) -> bool {
+    characters.next();
+    if characters.peek() == Some(&expected) {
+        characters.next();
+        true
+    } else {
+        false
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, FilterError> {
+    let mut tokens = Vec::new();
+    let mut characters = text.chars().peekable();
+
+    while let Some(&character) = characters.peek() {
+        if character.is_whitespace() {
+            characters.next();
+            continue;
+        }
+
+        let token = match character {
+            '(' => { characters.next(); Token::LeftParenthesis }
+            ')' => { characters.next(); Token::RightParenthesis }
+            '&' => if next_is('&', &mut characters) { Token::And } else { return Err(FilterError("expected `&&`".to_owned())) },
+            '|' => if next_is('|', &mut characters) { Token::Or } else { return Err(FilterError("expected `||`".to_owned())) },
+            '=' => {
+                characters.next();
+                match characters.peek() {
+                    Some('=') => { characters.next(); Token::Operator(Operator::Equal) }
+                    Some('~') => { characters.next(); Token::Operator(Operator::Matches) }
+                    _ => Token::Operator(Operator::Equal),
+                }
+            }
+            '!' => {
+                characters.next();
+                match characters.peek() {
+                    Some('=') => { characters.next(); Token::Operator(Operator::NotEqual) }
+                    Some('~') => { characters.next(); Token::Operator(Operator::DoesNotMatch) }
+                    _ => Token::Not,
+                }
+            }
+            '<' => if next_is('=', &mut characters) { Token::Operator(Operator::LessOrEqual) } else { Token::Operator(Operator::Less) },
+            '>' => if next_is('=', &mut characters) { Token::Operator(Operator::GreaterOrEqual) } else { Token::Operator(Operator::Greater) },
+            '"' | '\'' | '`' => {
+                characters.next();
+                let mut string = String::new();
+                loop {
+                    match characters.next() {
+                        Some('\\') => match characters.next() {
+                            Some(escaped) => string.push(escaped),
+                            None => return Err(FilterError("unfinished escape".to_owned())),
+                        },
+                        Some(closing) if closing == character => break,
+                        Some(other) => string.push(other),
+                        None => return Err(FilterError(format!("missing closing {}", character))),
+                    }
+                }
+                if character == '`' { Token::Header(string) } else { Token::Text(string) }
+            }
+            '#' => {
+                characters.next();
+                let mut digits = String::new();
+                while let Some(digit) = characters.peek().filter(|character| character.is_ascii_digit()) {
+                    digits.push(*digit);
+                    characters.next();
+                }
+                match digits.parse::<usize>() {
+                    Ok(index) if index > 0 => Token::Index(index),
+                    _ => return Err(FilterError("expected a column number after `#`".to_owned())),
+                }
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(character) = characters.peek()
+                    .filter(|character| !character.is_whitespace() && !"()&|=!<>\"'`".contains(**character)) {
+                    word.push(*character);
+                    characters.next();
+                }
+                match word.to_lowercase().as_str() {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    "contains" => Token::Operator(Operator::Contains),
+                    "startswith" => Token::Operator(Operator::StartsWith),
+                    "endswith" => Token::Operator(Operator::EndsWith),
+                    _ => Token::Word(word),
+                }
+            }
+        };
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+#[derive(Debug)]
+enum Operand {
+    Column(usize),
+    Text(String),
+}
+
+#[derive(Debug)]
+enum Expression {
+    And(Box<Expression>, Box<Expression>),
+    Or(Box<Expression>, Box<Expression>),
+    Not(Box<Expression>),
+    Compare(Operand, Operator, Operand),
+    Regex(Operand, Regex, bool), // Negated if the flag is false
+    Truthy(Operand),
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    position: usize,
+    csv: &'a CSVFile,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn or(&mut self) -> Result<Expression, FilterError> {
+        let mut expression = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            expression = Expression::Or(Box::new(expression), Box::new(self.and()?));
+        }
+        Ok(expression)
+    }
+
+    fn and(&mut self) -> Result<Expression, FilterError> {
+        let mut expression = self.not()?;
+        while self.peek() == Some(&Token::And) {
+            self.next();
+            expression = Expression::And(Box::new(expression), Box::new(self.not()?));
+        }
+        Ok(expression)
+    }
+
+    fn not(&mut self) -> Result<Expression, FilterError> {
+        if self.peek() == Some(&Token::Not) {
+            self.next();
+            return Ok(Expression::Not(Box::new(self.not()?)));
+        }
+        if self.peek() == Some(&Token::LeftParenthesis) {
+            self.next();
+            let expression = self.or()?;
+            return match self.next() {
+                Some(Token::RightParenthesis) => Ok(expression),
+                _ => Err(FilterError("missing `)`".to_owned())),
+            };
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expression, FilterError> {
+        if let Some(Token::Word(word)) = self.peek() {
+            if self.csv.column_named(word).is_none() {
+                return Err(FilterError(format!("no column named `{}`", word)));
+            }
+        }
+        let left = self.operand()?;
+        let operator = match self.peek() {
+            Some(Token::Operator(operator)) => *operator,
+            _ => return Parser::with_column(Expression::Truthy(left)),
+        };
+        self.next();
+
+        if operator == Operator::Matches || operator == Operator::DoesNotMatch {
+            let pattern = match self.next() {
+                Some(Token::Text(pattern)) | Some(Token::Word(pattern)) => pattern,
+                _ => return Err(FilterError("expected a quoted regular expression".to_owned())),
+            };
+            let regex = Regex::new(&pattern).map_err(|error| FilterError(error.to_string()))?;
+            return Parser::with_column(Expression::Regex(left, regex, operator == Operator::Matches));
+        }
+
+        let right = self.operand()?;
+        Parser::with_column(Expression::Compare(left, operator, right))
+    }
+
+    // Comparing text with text gives the same answer for every row, which is never what was meant.
+    fn with_column(expression: Expression) -> Result<Expression, FilterError> {
+        let has_column = match &expression {
+            Expression::Compare(left, _, right) => matches!(left, Operand::Column(_)) || matches!(right, Operand::Column(_)),
+            Expression::Regex(operand, _, _) | Expression::Truthy(operand) => matches!(operand, Operand::Column(_)),
+            _ => true,
+        };
+        if has_column {
+            Ok(expression)
+        } else {
+            Err(FilterError("expected a column on one side of the comparison".to_owned()))
+        }
+    }
+
+    fn operand(&mut self) -> Result<Operand, FilterError> {
+        match self.next() {
+            Some(Token::Text(text)) => Ok(Operand::Text(text)),
+            Some(Token::Word(word)) => Ok(self.csv.column_named(&word).map_or(Operand::Text(word), Operand::Column)),
+            Some(Token::Header(header)) => self.csv.column_named(&header)
+                .map(Operand::Column)
+                .ok_or_else(|| FilterError(format!("no column named `{}`", header))),
+            Some(Token::Index(index)) if index <= self.csv.column_count() => Ok(Operand::Column(index - 1)),
+            Some(Token::Index(index)) => Err(FilterError(format!("no column #{}", index))),
+            Some(token) => Err(FilterError(format!("unexpected {:?}", token))),
+            None => Err(FilterError("unexpected end of expression".to_owned())),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, PartialOrd)]
+enum Value<'a> {
+    Number(f64),
+    Date([u32; 7]),
+    Boolean(bool),
+    Text(&'a str),
+}
+
+// Values are read according to the type of the column they are compared with. Anything can be a
+// number or a date in a column of mixed (or unknown) type.
+fn typed_value(text: &str, column_type: Option<ColumnType>) -> Value<'_> {
+    let number = || parse_number(text).map(Value::Number);
+    let date = || parse_date(text).map(Value::Date);
+    let value = match column_type {
+        Some(column_type) if column_type.is_numeric() => number(),
+        Some(column_type) if column_type.is_temporal() => date(),
+        Some(ColumnType::Boolean) => parse_boolean(text).map(Value::Boolean),
+        Some(ColumnType::String) => None,
+        _ => number().or_else(date),
+    };
+    value.unwrap_or(Value::Text(text))
+}
+
+// Values of different types (`N/A` and `2`, say) are not comparable, so they are neither equal nor
+// ordered.
+fn compare(left: &str, right: &str, column_type: Option<ColumnType>) -> Option<Ordering> {
+    let left = typed_value(left, column_type);
+    let right = typed_value(right, column_type);
+    if std::mem::discriminant(&left) != std::mem::discriminant(&right) {
+        return None;
+    }
+    left.partial_cmp(&right)
+}
+
+impl Expression {
+    fn value(operand: &Operand, csv: &CSVFile, row_index: usize) -> String {
+        match operand {
+            Operand::Text(text) => text.clone(),
+            Operand::Column(column_index) => csv.get_column(*column_index)
+                .and_then(|column| column.value(row_index))
+                .map_or_else(String::new, |item| item.text()),
+        }
+    }
+
+    fn column_type(operand: &Operand, csv: &CSVFile) -> Option<ColumnType> {
+        match operand {
+            Operand::Text(_) => None,
+            Operand::Column(column_index) => csv.get_column(*column_index).map(|column| column.column_type()),
+        }
+    }
+
+    fn matches(&self, csv: &CSVFile, row_index: usize) -> bool {
+        match self {
+            Expression::And(left, right) => left.matches(csv, row_index) && right.matches(csv, row_index),
+            Expression::Or(left, right) => left.matches(csv, row_index) || right.matches(csv, row_index),
+            Expression::Not(expression) => !expression.matches(csv, row_index),
+            Expression::Regex(operand, regex, expected) => {
+                regex.is_match(&Expression::value(operand, csv, row_index)) == *expected
+            }
+            Expression::Truthy(operand) => {
+                let value = Expression::value(operand, csv, row_index);
+                let value = value.trim();
+                !(value.is_empty() || value == "0" || parse_boolean(value) == Some(false))
+            }
+            Expression::Compare(left, operator, right) => {
+                let column_type = Expression::column_type(left, csv).or_else(|| Expression::column_type(right, csv));
+                let left = Expression::value(left, csv, row_index);
+                let right = Expression::value(right, csv, row_index);
+                match operator {
+                    Operator::Equal => compare(&left, &right, column_type) == Some(Ordering::Equal),
+                    Operator::NotEqual => compare(&left, &right, column_type) != Some(Ordering::Equal),
+                    Operator::Less => compare(&left, &right, column_type) == Some(Ordering::Less),
+                    Operator::LessOrEqual => matches!(compare(&left, &right, column_type), Some(Ordering::Less) | Some(Ordering::Equal)),
+                    Operator::Greater => compare(&left, &right, column_type) == Some(Ordering::Greater),
+                    Operator::GreaterOrEqual => matches!(compare(&left, &right, column_type), Some(Ordering::Greater) | Some(Ordering::Equal)),
+                    Operator::Contains => left.contains(right.as_str()),
+                    Operator::StartsWith => left.starts_with(right.as_str()),
+                    Operator::EndsWith => left.ends_with(right.as_str()),
+                    Operator::Matches | Operator::DoesNotMatch => unreachable!("regular expressions are parsed into Expression::Regex"),
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Filter {
+    text: String,
+    expression: Expression,
+}
+
+impl Filter {
+    // Column names are resolved against the headers of `csv` right away.
+    pub fn parse(text: &str, csv: &CSVFile) -> Result<Self, FilterError> {
+        let tokens = tokenize(text)?;
+        if tokens.is_empty() {
+            return Err(FilterError("empty expression".to_owned()));
+        }
+        let mut parser = Parser { tokens, position: 0, csv };
+        let expression = parser.or()?;
+        if let Some(token) = parser.peek() {
+            return Err(FilterError(format!("unexpected {:?}", token)));
+        }
+        Ok(Filter { text: text.to_owned(), expression })
+    }
+
+    pub fn text(&self) -> &str {
+        self.text.as_str()
+    }
+
+    pub fn matches(&self, csv: &CSVFile, row_index: usize) -> bool {
+        self.expression.matches(csv, row_index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::csv::tests::csv;
+
+    fn people() -> CSVFile {
+        csv("name,age,city,joined,score\n\
+             alice,30,New York,2021-03-01,7\n\
+             bob,N/A,Paris,2020-12-31,10\n\
+             carol,9,Oslo,2021-07-15,x\n\
+             \"dan \"\"d\"\"\",100,New York,2019-01-01,\n")
+    }
+
+    fn matching(text: &str) -> Vec<usize> {
+        let csv = people();
+        let filter = Filter::parse(text, &csv).unwrap_or_else(|error| panic!("{}: {}", text, error));
+        (0..csv.row_count()).filter(|&row_index| filter.matches(&csv, row_index)).collect()
+    }
+
+    fn error(text: &str) -> String {
+        Filter::parse(text, &people()).err().unwrap_or_else(|| panic!("{} should not parse", text)).to_string()
+    }
+
+    #[test]
+    fn tokenizes_operators_and_words() {
+        let tokens = tokenize("a>=1 && !(b != 'x') || c =~ \"^y\"").unwrap();
+        assert_eq!(tokens, vec![
+            Token::Word("a".to_owned()), Token::Operator(Operator::GreaterOrEqual), Token::Word("1".to_owned()),
+            Token::And, Token::Not, Token::LeftParenthesis,
+            Token::Word("b".to_owned()), Token::Operator(Operator::NotEqual), Token::Text("x".to_owned()),
+            Token::RightParenthesis, Token::Or,
+            Token::Word("c".to_owned()), Token::Operator(Operator::Matches), Token::Text("^y".to_owned()),
+        ]);
+        assert_eq!(tokenize("a AND b or NOT c contains d").unwrap(), vec![
+            Token::Word("a".to_owned()), Token::And, Token::Word("b".to_owned()), Token::Or, Token::Not,
+            Token::Word("c".to_owned()), Token::Operator(Operator::Contains), Token::Word("d".to_owned()),
+        ]);
+    }
+
+    #[test]
+    fn tokenizes_quotes_and_escapes() {
+        assert_eq!(tokenize(r#""say \"hi\"""#).unwrap(), vec![Token::Text("say \"hi\"".to_owned())]);
+        assert_eq!(tokenize(r"'it\'s' `a \` b`").unwrap(), vec![Token::Text("it's".to_owned()), Token::Header("a ` b".to_owned())]);
+        assert_eq!(tokenize(r#""back\\slash""#).unwrap(), vec![Token::Text("back\\slash".to_owned())]);
+        assert!(tokenize("\"unfinished").is_err());
+        assert!(tokenize("\"escape\\").is_err());
+        assert!(tokenize("a & b").is_err());
+        assert!(tokenize("a | b").is_err());
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_eq!(matching("city == Oslo || city == \"New York\" && age > 50"), vec![2, 3]);
+        assert_eq!(matching("(city == Oslo || city == \"New York\") && age > 50"), vec![3]);
+        assert_eq!(matching("not city == Paris && age < 50"), vec![0, 2]);
+        assert_eq!(matching("not (city == Paris || age < 50)"), vec![3]);
+    }
+
+    #[test]
+    fn quoted_values_and_headers() {
+        assert_eq!(matching(r#"name == "dan \"d\"""#), vec![3]);
+        assert_eq!(matching("`city` == 'New York'"), vec![0, 3]);
+        assert_eq!(matching("CITY == Paris"), vec![1]);
+        assert_eq!(error("`town` == Paris"), "Invalid filter: no column named `town`");
+    }
+
+    #[test]
+    fn columns_by_number() {
+        assert_eq!(matching("#3 == Oslo"), vec![2]);
+        assert_eq!(matching("#1 startswith a || #1 endswith l"), vec![0, 2]);
+        assert_eq!(error("#6 == 1"), "Invalid filter: no column #6");
+        assert!(tokenize("#0").is_err());
+        assert!(tokenize("# 1").is_err());
+    }
+
+    #[test]
+    fn compares_numbers_and_dates_by_value() {
+        assert_eq!(matching("age > 10"), vec![0, 3]);
+        assert_eq!(matching("age <= 30"), vec![0, 2]);
+        assert_eq!(matching("joined < 2021-01-01"), vec![1, 3]);
+        assert_eq!(matching("joined >= \"2021-03-01\""), vec![0, 2]);
+    }
+
+    #[test]
+    fn values_of_different_types_are_neither_equal_nor_ordered() {
+        assert_eq!(matching("age < 1000"), vec![0, 2, 3]);
+        assert_eq!(matching("age >= 0"), vec![0, 2, 3]);
+        assert_eq!(matching("age != 30"), vec![1, 2, 3]);
+        assert_eq!(matching("age == N/A"), vec![1]);
+        assert_eq!(matching("score > 5"), vec![0, 1]);
+        assert_eq!(compare("10", "9", None), Some(Ordering::Greater));
+        assert_eq!(compare("10", "9", Some(ColumnType::String)), Some(Ordering::Less));
+        assert_eq!(compare("N/A", "2", Some(ColumnType::Integer)), None);
+        assert_eq!(compare("yes", "true", Some(ColumnType::Boolean)), Some(Ordering::Equal));
+    }
+
+    #[test]
+    fn regular_expressions() {
+        assert_eq!(matching("name =~ \"^[ab]\""), vec![0, 1]);
+        assert_eq!(matching("name !~ \"^[ab]\""), vec![2, 3]);
+        assert_eq!(matching("joined =~ '^2021-0[1-6]'"), vec![0]);
+        assert!(error("name =~ \"(\"").starts_with("Invalid filter: "));
+        assert_eq!(error("name =~ #1"), "Invalid filter: expected a quoted regular expression");
+    }
+
+    #[test]
+    fn truthy_columns() {
+        assert_eq!(matching("score"), vec![0, 1, 2]);
+        assert_eq!(matching("!score"), vec![3]);
+    }
+
+    #[test]
+    fn misspelled_columns_are_errors() {
+        assert_eq!(error("nosuch > 1"), "Invalid filter: no column named `nosuch`");
+        assert_eq!(error("nosuch"), "Invalid filter: no column named `nosuch`");
+        assert_eq!(error("'a' == b"), "Invalid filter: expected a column on one side of the comparison");
+        assert_eq!(error("\"text\""), "Invalid filter: expected a column on one side of the comparison");
+    }
+
+    #[test]
+    fn malformed_expressions_are_errors() {
+        assert_eq!(error(""), "Invalid filter: empty expression");
+        assert_eq!(error("(age > 1"), "Invalid filter: missing `)`");
+        assert_eq!(error("age >"), "Invalid filter: unexpected end of expression");
+        assert_eq!(error("age > 1 )"), "Invalid filter: unexpected RightParenthesis");
+    }
+}
@@ -0,0 +1,62 @@
+use std::collections::HashMap;
+
+use crate::csv::CSVFile;
+use crate::stats::format_number;
+use crate::types::parse_number;
+
+#[derive(Default)]
+struct Group {
+    count: usize,
+    sum: f64,
+    numbers: usize, // How many of the aggregated values were numbers
+}
+
+// Builds a table of the distinct values of `column_index` among `rows`, with how many of the rows
+// have each value, most common first. With an `aggregate` column, the table also has the sum and
+// average of the numbers in that column for each value. Missing values count as empty.
+pub fn frequency_table(csv: &CSVFile, rows: &[usize], column_index: usize, aggregate: Option<usize>) -> CSVFile {
+    let text = |column_index: usize, row_index: usize| csv.get_column(column_index)
+        .and_then(|column| column.value(row_index))
+        .map_or_else(String::new, |item| item.text());
+
+    let mut groups: HashMap<String, Group> = HashMap::new();
+    for &row_index in rows {
+        let group = groups.entry(text(column_index, row_index)).or_default();
+        group.count += 1;
+        if let Some(number) = aggregate.and_then(|aggregate| parse_number(&text(aggregate, row_index))) {
+            group.sum += number;
+            group.numbers += 1;
+        }
+    }
+
+    let mut groups: Vec<(String, Group)> = groups.into_iter().collect();
+    groups.sort_by(|(left_value, left), (right_value, right)| {
+        right.count.cmp(&left.count).then_with(|| left_value.cmp(right_value))
+    });
+
+    let mut table = CSVFile::new();
+    table.new_column(csv.column_name(column_index));
+    table.new_column("count".to_owned());
+    table.new_column("percent".to_owned());
+    if let Some(aggregate) = aggregate {
+        let name = csv.column_name(aggregate);
+        table.new_column(format!("sum({})", name));
+        table.new_column(format!("avg({})", name));
+    }
+
+    for (value, group) in groups {
+        let percent = group.count as f64 * 100.0 / std::cmp::max(rows.len(), 1) as f64;
+        let mut record = vec![value, group.count.to_string(), format_number(percent)];
+        if aggregate.is_some() {
+            if group.numbers > 0 {
+                record.push(format_number(group.sum));
+                record.push(format_number(group.sum / group.numbers as f64));
+            } else {
+                record.push(String::new());
+                record.push(String::new());
+            }
+        }
+        table.push_row(record);
+    }
+    table
+}
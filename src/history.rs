@@ -0,0 +1,341 @@
+use std::collections::VecDeque;
+
+use crate::csv::{CSVColumn, CSVFile};
+use crate::sort::SortKey;
+
+// Roughly how much memory the undo history may take up. The oldest changes are forgotten first,
+// but the latest one is always kept, however large.
+pub const HISTORY_BUDGET: usize = 64 * 1024 * 1024;
+
+// Bookkeeping per value and per change, on top of the text itself.
+const VALUE_OVERHEAD: usize = 32;
+const CHANGE_OVERHEAD: usize = 64;
+
+#[derive(Debug)]
+pub enum Change {
+    Value { row: usize, column: usize, before: Option<String>, after: String }, // `None` for a missing value
+    Header { column: usize, before: String, after: String },
+    InsertRow { row: usize, fields: Vec<String> },
+    DeleteRow { row: usize, fields: Vec<String> },
+    InsertColumn { column: usize, header: String },
+    DeleteColumn { column: usize, values: CSVColumn }, // Empty while the column is back in the file
+    Sort { before: Vec<SortKey>, after: Vec<SortKey> }, // Only changes the display, which has to apply it
+}
+
+impl Change {
+    fn size(&self) -> usize {
+        let fields = |fields: &[String]| fields.iter().map(|field| field.len() + VALUE_OVERHEAD).sum::<usize>();
+        CHANGE_OVERHEAD + match self {
+            Change::Value { before, after, .. } => before.as_ref().map_or(0, String::len) + after.len(),
+            Change::Header { before, after, .. } => before.len() + after.len(),
+            Change::InsertRow { fields: row, .. } | Change::DeleteRow { fields: row, .. } => fields(row),
+            Change::InsertColumn { header, .. } => header.len(),
+            Change::DeleteColumn { values, .. } => values.header().len() + values.values()
+                .map(|item| item.lines().map(|line| line.len()).sum::<usize>() + VALUE_OVERHEAD)
+                .sum::<usize>(),
+            Change::Sort { before, after } => (before.len() + after.len()) * std::mem::size_of::<SortKey>(),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Change::Value { row, column, .. } => format!("edit of row {}, column {}", row + 1, column + 1),
+            Change::Header { column, .. } => format!("renaming of column {}", column + 1),
+            Change::InsertRow { row, .. } => format!("insertion of row {}", row + 1),
+            Change::DeleteRow { row, .. } => format!("deletion of row {}", row + 1),
+            Change::InsertColumn { column, .. } => format!("insertion of column {}", column + 1),
+            Change::DeleteColumn { column, .. } => format!("deletion of column {}", column + 1),
+            Change::Sort { .. } => "sort".to_owned(),
+        }
+    }
+
+    // Makes the change to `csv` (again) and returns it, ready to be undone.
+    pub fn apply(self, csv: &mut CSVFile) -> Change {
+        match self {
+            Change::Value { row, column, before, after } => {
+                csv.set_value(row, column, &after);
+                Change::Value { row, column, before, after }
+            }
+            Change::Header { column, before, after } => {
+                csv.set_header(column, after.clone());
+                Change::Header { column, before, after }
+            }
+            Change::InsertRow { row, fields } => {
+                csv.insert_row(row, fields.clone());
+                Change::InsertRow { row, fields }
+            }
+            Change::DeleteRow { row, .. } => {
+                let fields = csv.delete_row(row);
+                Change::DeleteRow { row, fields }
+            }
+            Change::InsertColumn { column, header } => {
+                csv.insert_column(column, header.clone());
+                Change::InsertColumn { column, header }
+            }
+            Change::DeleteColumn { column, .. } => {
+                let values = csv.delete_column(column).unwrap_or_default();
+                Change::DeleteColumn { column, values }
+            }
+            change @ Change::Sort { .. } => change,
+        }
+    }
+
+    // Takes the change back from `csv` and returns it, ready to be redone.
+    pub fn revert(self, csv: &mut CSVFile) -> Change {
+        match self {
+            Change::Value { row, column, before, after } => {
+                match &before {
+                    Some(before) => { csv.set_value(row, column, before); }
+                    None => csv.clear_value(row, column),
+                }
+                Change::Value { row, column, before, after }
+            }
+            Change::Header { column, before, after } => {
+                csv.set_header(column, before.clone());
+                Change::Header { column, before, after }
+            }
+            Change::InsertRow { row, .. } => {
+                let fields = csv.delete_row(row);
+                Change::InsertRow { row, fields }
+            }
+            Change::DeleteRow { row, fields } => {
+                csv.insert_row(row, fields.clone());
+                Change::DeleteRow { row, fields }
+            }
+            Change::InsertColumn { column, header } => {
+                csv.delete_column(column);
+                Change::InsertColumn { column, header }
+            }
+            Change::DeleteColumn { column, values } => {
+                csv.restore_column(column, values);
+                Change::DeleteColumn { column, values: CSVColumn::default() }
+            }
+            change @ Change::Sort { .. } => change,
+        }
+    }
+}
+
+// A change as kept in the history, with the versions of the file before and after it. Sorting
+// leaves the file as it is, so its versions are the same.
+#[derive(Debug)]
+pub struct Step {
+    pub change: Change,
+    before: u64,
+    after: u64,
+}
+
+// Changes that can be undone, latest last, and changes that were undone and can be redone until
+// something else changes. Each change to the file makes a new version of it, so that undoing back
+// to the version that was saved leaves nothing to save.
+#[derive(Debug)]
+pub struct History {
+    done: VecDeque<Step>,
+    undone: Vec<Step>,
+    size: usize, // Of all the changes kept
+    budget: usize,
+    version: u64, // Of the file as it is now
+    last_version: u64,
+    saved_version: Option<u64>, // None once the saved version cannot be got back to
+}
+
+impl History {
+    pub fn new(budget: usize) -> Self {
+        History { done: VecDeque::new(), undone: Vec::new(), size: 0, budget, version: 0, last_version: 0, saved_version: Some(0) }
+    }
+
+    pub fn record(&mut self, change: Change) {
+        for step in self.undone.drain(..) {
+            self.size -= step.change.size();
+            if Some(step.after) == self.saved_version {
+                self.saved_version = None;
+            }
+        }
+        let before = self.version;
+        if !matches!(change, Change::Sort { .. }) {
+            self.last_version += 1;
+            self.version = self.last_version;
+        }
+        self.push_done(Step { change, before, after: self.version });
+    }
+
+    fn push_done(&mut self, step: Step) {
+        self.size += step.change.size();
+        self.done.push_back(step);
+        while self.size > self.budget && self.done.len() > 1 {
+            if let Some(forgotten) = self.done.pop_front() {
+                self.size -= forgotten.change.size();
+                if Some(forgotten.before) == self.saved_version {
+                    self.saved_version = None;
+                }
+            }
+        }
+    }
+
+    // The change `take_undo` would give, to get ready for it.
+    pub fn next_undo(&self) -> Option<&Change> {
+        self.done.back().map(|step| &step.change)
+    }
+
+    // The change `take_redo` would give.
+    pub fn next_redo(&self) -> Option<&Change> {
+        self.undone.last().map(|step| &step.change)
+    }
+
+    // The change to undo, which should then be handed back with `undone`.
+    pub fn take_undo(&mut self) -> Option<Step> {
+        let step = self.done.pop_back()?;
+        self.size -= step.change.size();
+        self.version = step.before;
+        Some(step)
+    }
+
+    pub fn undone(&mut self, step: Step) {
+        self.size += step.change.size();
+        self.undone.push(step);
+    }
+
+    // The change to redo, which should then be handed back with `redone`.
+    pub fn take_redo(&mut self) -> Option<Step> {
+        let step = self.undone.pop()?;
+        self.size -= step.change.size();
+        self.version = step.after;
+        Some(step)
+    }
+
+    pub fn redone(&mut self, step: Step) {
+        self.push_done(step);
+    }
+
+    pub fn saved(&mut self) {
+        self.saved_version = Some(self.version);
+    }
+
+    // Whether the file differs from the version last saved (or read).
+    pub fn is_modified(&self) -> bool {
+        self.saved_version != Some(self.version)
+    }
+}
+
+impl Default for History {
+    fn default() -> Self {
+        History::new(HISTORY_BUDGET)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::csv::tests::csv;
+
+    fn text(csv: &CSVFile) -> String {
+        let mut writer = ::csv::WriterBuilder::new().flexible(true).from_writer(Vec::new());
+        csv.write(&mut writer, true).unwrap();
+        String::from_utf8(writer.into_inner().unwrap()).unwrap()
+    }
+
+    fn rename(after: &str) -> Change {
+        Change::Header { column: 0, before: String::new(), after: after.to_owned() }
+    }
+
+    // Only the bookkeeping, without a file to change.
+    fn undo(history: &mut History) -> bool {
+        history.take_undo().map(|step| history.undone(step)).is_some()
+    }
+
+    fn redo(history: &mut History) -> bool {
+        history.take_redo().map(|step| history.redone(step)).is_some()
+    }
+
+    #[test]
+    fn undoing_back_to_the_saved_version_leaves_nothing_to_save() {
+        let mut history = History::default();
+        assert!(!history.is_modified());
+        history.record(rename("a"));
+        history.saved();
+        assert!(!history.is_modified());
+
+        history.record(rename("b"));
+        assert!(history.is_modified());
+        assert!(undo(&mut history));
+        assert!(!history.is_modified());
+        assert!(undo(&mut history));
+        assert!(history.is_modified());
+        assert!(redo(&mut history));
+        assert!(!history.is_modified());
+        assert!(redo(&mut history));
+        assert!(history.is_modified());
+    }
+
+    #[test]
+    fn sorting_leaves_nothing_to_save() {
+        let mut history = History::default();
+        history.record(Change::Sort { before: Vec::new(), after: Vec::new() });
+        assert!(!history.is_modified());
+        history.record(rename("a"));
+        assert!(undo(&mut history));
+        assert!(!history.is_modified());
+    }
+
+    #[test]
+    fn forgetting_the_saved_version_leaves_the_file_modified_for_good() {
+        let size = rename("a").size();
+        let mut history = History::new(size + size / 2);
+        history.record(rename("a"));
+        history.record(rename("b"));
+        assert_eq!(history.done.len(), 1);
+        assert_eq!(history.size, size);
+
+        assert!(undo(&mut history));
+        assert!(!undo(&mut history));
+        assert!(history.is_modified());
+        assert!(redo(&mut history));
+        assert!(history.is_modified());
+    }
+
+    #[test]
+    fn the_latest_change_is_kept_however_large() {
+        let mut history = History::new(1);
+        history.record(rename("a"));
+        history.record(rename("b"));
+        assert_eq!(history.done.len(), 1);
+        assert!(undo(&mut history));
+        assert_eq!(history.size, rename("b").size());
+    }
+
+    #[test]
+    fn a_new_change_clears_what_could_be_redone() {
+        let mut history = History::default();
+        history.record(rename("a"));
+        history.saved();
+        assert!(undo(&mut history));
+        history.record(rename("b"));
+        assert!(!redo(&mut history));
+        assert_eq!(history.size, rename("b").size());
+
+        // The saved version was among the changes cleared, so there is no way back to it.
+        assert!(history.is_modified());
+        assert!(undo(&mut history));
+        assert!(history.is_modified());
+    }
+
+    #[test]
+    fn undoing_an_edit_past_a_short_row_keeps_it_short() {
+        let mut csv = csv("a,b,c\n1,2\n");
+        let mut history = History::default();
+        let before = csv.set_value(0, 2, "3");
+        assert_eq!(before, None);
+        history.record(Change::Value { row: 0, column: 2, before, after: "3".to_owned() });
+        assert_eq!(text(&csv), "a,b,c\n1,2,3\n");
+
+        let mut step = history.take_undo().unwrap();
+        step.change = step.change.revert(&mut csv);
+        history.undone(step);
+        assert_eq!(text(&csv), "a,b,c\n1,2\n");
+        assert!(!history.is_modified());
+
+        let mut step = history.take_redo().unwrap();
+        step.change = step.change.apply(&mut csv);
+        history.redone(step);
+        assert_eq!(text(&csv), "a,b,c\n1,2,3\n");
+    }
+}
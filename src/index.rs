@@ -0,0 +1,350 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+pub const INDEX_EXTENSION: &str = "hamsteridx";
+
+const MAGIC: &[u8; 8] = b"HAMSTIDX";
+const VERSION: u32 = 2;
+
+// Only every this many rows has its offset kept. Getting to any other row means reading on from
+// the one before it, which is quick, while the index stays small even for huge files.
+pub const INDEX_STRIDE: usize = 256;
+
+// Bytes per offset in the cache file.
+const OFFSET_SIZE: u64 = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RowOffset {
+    pub byte: u64,
+    pub line: u64,
+}
+
+// Identifies the exact file contents and the dialect the index was built with. If any of these
+// change, the cached offsets no longer point at record boundaries.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct IndexKey {
+    file_size: u64,
+    modified_seconds: u64,
+    modified_nanoseconds: u32,
+    dialect: Vec<u8>,
+}
+
+impl IndexKey {
+    fn of(csv_path: &Path, dialect: Vec<u8>) -> Option<Self> {
+        let metadata = std::fs::metadata(csv_path).ok()?;
+        let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        Some(IndexKey {
+            file_size: metadata.len(),
+            modified_seconds: modified.as_secs(),
+            modified_nanoseconds: modified.subsec_nanos(),
+            dialect,
+        })
+    }
+
+    fn write_to(&self, writer: &mut impl Write) -> std::io::Result<()> {
+        writer.write_u64::<LittleEndian>(self.file_size)?;
+        writer.write_u64::<LittleEndian>(self.modified_seconds)?;
+        writer.write_u32::<LittleEndian>(self.modified_nanoseconds)?;
+        writer.write_u32::<LittleEndian>(self.dialect.len() as u32)?;
+        writer.write_all(&self.dialect)
+    }
+
+    fn read_from(reader: &mut impl Read) -> std::io::Result<Self> {
+        let file_size = reader.read_u64::<LittleEndian>()?;
+        let modified_seconds = reader.read_u64::<LittleEndian>()?;
+        let modified_nanoseconds = reader.read_u32::<LittleEndian>()?;
+        let mut dialect = Vec::new();
+        let dialect_length = reader.read_u32::<LittleEndian>()? as u64;
+        reader.take(dialect_length).read_to_end(&mut dialect)?;
+        if dialect.len() as u64 != dialect_length {
+            return Err(invalid("truncated"));
+        }
+        Ok(IndexKey { file_size, modified_seconds, modified_nanoseconds, dialect })
+    }
+}
+
+fn invalid(reason: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, reason)
+}
+
+// Start offsets of every `INDEX_STRIDE`th record: row `n * INDEX_STRIDE` of the CSV file starts at
+// `checkpoints[n]`. The index can be partial: it grows as rows are read and is only persisted once
+// it covers the whole file.
+#[derive(Debug, Default)]
+pub struct RowIndex {
+    checkpoints: Vec<RowOffset>,
+    rows: usize, // Rows indexed so far
+    last: Option<RowOffset>, // Of the last row indexed
+    complete: bool,
+    cache: Option<(PathBuf, IndexKey)>,
+}
+
+impl RowIndex {
+    pub fn cache_path(csv_path: &Path) -> PathBuf {
+        let mut path = csv_path.as_os_str().to_owned();
+        path.push(".");
+        path.push(INDEX_EXTENSION);
+        PathBuf::from(path)
+    }
+
+    // Reuses the cached index next to `csv_path` if it is still valid, otherwise starts an empty
+    // index that will be cached once complete.
+    pub fn open(csv_path: &Path, dialect: Vec<u8>) -> Self {
+        let key = match IndexKey::of(csv_path, dialect) {
+            Some(key) => key,
+            None => return RowIndex::default(),
+        };
+        let cache_path = RowIndex::cache_path(csv_path);
+
+        match RowIndex::load(&cache_path, &key) {
+            Ok(mut index) => {
+                log::info!("Using row index {:?} ({} rows)", cache_path, index.rows);
+                index.cache = Some((cache_path, key));
+                index
+            }
+            Err(error) => {
+                log::info!("Not using row index {:?}: {}", cache_path, error);
+                RowIndex { cache: Some((cache_path, key)), ..RowIndex::default() }
+            }
+        }
+    }
+
+    // The counts in the file are checked against its size before anything is allocated, so that
+    // a corrupt cache is rejected rather than trusted.
+    fn load(cache_path: &Path, expected_key: &IndexKey) -> std::io::Result<RowIndex> {
+        let file = File::open(cache_path)?;
+        let cache_size = file.metadata()?.len();
+        let mut reader = BufReader::new(file);
+
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        let version = reader.read_u32::<LittleEndian>()?;
+        if &magic != MAGIC || version != VERSION {
+            return Err(invalid("unrecognized format"));
+        }
+
+        if IndexKey::read_from(&mut reader)? != *expected_key {
+            return Err(invalid("stale index"));
+        }
+
+        let stride = reader.read_u64::<LittleEndian>()?;
+        let row_count = reader.read_u64::<LittleEndian>()?;
+        if stride != INDEX_STRIDE as u64 {
+            return Err(invalid("different stride"));
+        }
+        // Every record but the last ends with a terminator, so there cannot be more of them than bytes.
+        if row_count > expected_key.file_size.saturating_add(1) {
+            return Err(invalid("more rows than bytes"));
+        }
+        let checkpoint_count = (row_count + stride - 1) / stride;
+        let header_size = 8 + 4 + 8 + 8 + 4 + 4 + expected_key.dialect.len() as u64 + 8 + 8;
+        let expected_size = checkpoint_count.checked_add(1)
+            .and_then(|count| count.checked_mul(OFFSET_SIZE))
+            .and_then(|size| size.checked_add(header_size));
+        if expected_size != Some(cache_size) {
+            return Err(invalid("truncated"));
+        }
+
+        let mut read_offset = || -> std::io::Result<RowOffset> {
+            Ok(RowOffset { byte: reader.read_u64::<LittleEndian>()?, line: reader.read_u64::<LittleEndian>()? })
+        };
+        let last = read_offset()?;
+        let checkpoints = (0..checkpoint_count).map(|_| read_offset()).collect::<std::io::Result<Vec<RowOffset>>>()?;
+        Ok(RowIndex {
+            checkpoints,
+            rows: row_count as usize,
+            last: if row_count > 0 { Some(last) } else { None },
+            complete: true,
+            cache: None,
+        })
+    }
+
+    fn save(&self) -> std::io::Result<()> {
+        let (cache_path, key) = match &self.cache {
+            Some(cache) => cache,
+            None => return Ok(()),
+        };
+
+        let mut writer = BufWriter::new(File::create(cache_path)?);
+        writer.write_all(MAGIC)?;
+        writer.write_u32::<LittleEndian>(VERSION)?;
+        key.write_to(&mut writer)?;
+        writer.write_u64::<LittleEndian>(INDEX_STRIDE as u64)?;
+        writer.write_u64::<LittleEndian>(self.rows as u64)?;
+        let last = self.last.unwrap_or(RowOffset { byte: 0, line: 0 });
+        for offset in std::iter::once(&last).chain(self.checkpoints.iter()) {
+            writer.write_u64::<LittleEndian>(offset.byte)?;
+            writer.write_u64::<LittleEndian>(offset.line)?;
+        }
+        writer.flush()
+    }
+
+    // Rows indexed so far.
+    pub fn len(&self) -> usize {
+        self.rows
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows == 0
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    // The closest row at or before `row_index` that the index has the offset of, and that offset.
+    pub fn checkpoint(&self, row_index: usize) -> Option<(usize, RowOffset)> {
+        if row_index >= self.rows {
+            return None;
+        }
+        let checkpoint = row_index / INDEX_STRIDE;
+        self.checkpoints.get(checkpoint).map(|offset| (checkpoint * INDEX_STRIDE, *offset))
+    }
+
+    pub fn last(&self) -> Option<RowOffset> {
+        self.last
+    }
+
+    // Adds the offset of the next row.
+    pub fn push(&mut self, offset: RowOffset) {
+        if self.complete {
+            return;
+        }
+        if self.rows % INDEX_STRIDE == 0 {
+            self.checkpoints.push(offset);
+        }
+        self.last = Some(offset);
+        self.rows += 1;
+    }
+
+    // Called when the reader hits the end of the file with every row accounted for.
+    pub fn mark_complete(&mut self) {
+        if self.complete {
+            return;
+        }
+        self.complete = true;
+        if let Err(error) = self.save() {
+            // Read-only directories are fine, we just rebuild the index next time.
+            log::warn!("Cannot save row index: {}", error);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIALECT: &[u8] = b",\"";
+
+    // A CSV file with a complete index cached next to it, for `rows` rows.
+    struct Cached {
+        csv_path: PathBuf,
+        cache_path: PathBuf,
+    }
+
+    impl Cached {
+        fn new(name: &str, rows: usize) -> Self {
+            let csv_path = std::env::temp_dir().join(format!("hamstercsv-{}-{}.csv", std::process::id(), name));
+            std::fs::write(&csv_path, "x\n".repeat(rows)).unwrap();
+            let mut index = RowIndex::open(&csv_path, DIALECT.to_vec());
+            for row in 0..rows {
+                index.push(offset(row));
+            }
+            index.mark_complete();
+            Cached { cache_path: RowIndex::cache_path(&csv_path), csv_path }
+        }
+
+        fn key(&self) -> IndexKey {
+            IndexKey::of(&self.csv_path, DIALECT.to_vec()).unwrap()
+        }
+
+        fn rejection(&self) -> String {
+            RowIndex::load(&self.cache_path, &self.key()).unwrap_err().to_string()
+        }
+
+        // Overwrites the number at `position` in the header, counted from after the key.
+        fn overwrite(&self, position: u64, number: u64) {
+            let mut bytes = std::fs::read(&self.cache_path).unwrap();
+            let start = (8 + 4 + 8 + 8 + 4 + 4 + DIALECT.len() as u64 + position) as usize;
+            bytes[start..start + 8].copy_from_slice(&number.to_le_bytes());
+            std::fs::write(&self.cache_path, bytes).unwrap();
+        }
+    }
+
+    impl Drop for Cached {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.csv_path);
+            let _ = std::fs::remove_file(&self.cache_path);
+        }
+    }
+
+    fn offset(row: usize) -> RowOffset {
+        RowOffset { byte: row as u64 * 2, line: row as u64 + 1 }
+    }
+
+    #[test]
+    fn saves_and_reloads_every_strideth_offset() {
+        let cached = Cached::new("reload", 600);
+        let index = RowIndex::open(&cached.csv_path, DIALECT.to_vec());
+        assert!(index.is_complete());
+        assert_eq!(index.len(), 600);
+        assert_eq!(index.checkpoint(0), Some((0, offset(0))));
+        assert_eq!(index.checkpoint(300), Some((256, offset(256))));
+        assert_eq!(index.checkpoint(599), Some((512, offset(512))));
+        assert_eq!(index.checkpoint(600), None);
+        assert_eq!(index.last(), Some(offset(599)));
+    }
+
+    #[test]
+    fn reloads_an_empty_file() {
+        let cached = Cached::new("empty", 0);
+        let index = RowIndex::open(&cached.csv_path, DIALECT.to_vec());
+        assert!(index.is_complete());
+        assert_eq!((index.len(), index.last()), (0, None));
+    }
+
+    #[test]
+    fn rejects_a_stale_key() {
+        let cached = Cached::new("stale", 10);
+        assert!(!RowIndex::open(&cached.csv_path, b";\"".to_vec()).is_complete());
+
+        std::fs::write(&cached.csv_path, "x\n".repeat(11)).unwrap();
+        assert_eq!(cached.rejection(), "stale index");
+        assert!(!RowIndex::open(&cached.csv_path, DIALECT.to_vec()).is_complete());
+    }
+
+    #[test]
+    fn rejects_a_different_stride() {
+        let cached = Cached::new("stride", 10);
+        cached.overwrite(0, INDEX_STRIDE as u64 / 2);
+        assert_eq!(cached.rejection(), "different stride");
+    }
+
+    #[test]
+    fn rejects_a_truncated_file() {
+        let cached = Cached::new("truncated", 600);
+        let bytes = std::fs::read(&cached.cache_path).unwrap();
+        std::fs::write(&cached.cache_path, &bytes[..bytes.len() - 1]).unwrap();
+        assert_eq!(cached.rejection(), "truncated");
+
+        std::fs::write(&cached.cache_path, &bytes[..20]).unwrap();
+        assert!(RowIndex::load(&cached.cache_path, &cached.key()).is_err());
+    }
+
+    #[test]
+    fn rejects_more_rows_than_the_file_has_bytes() {
+        let cached = Cached::new("rows", 600);
+        cached.overwrite(8, 1202);
+        assert_eq!(cached.rejection(), "more rows than bytes");
+        cached.overwrite(8, u64::MAX);
+        assert_eq!(cached.rejection(), "more rows than bytes");
+
+        // Within bounds, but without the offsets to go with it.
+        cached.overwrite(8, 1201);
+        assert_eq!(cached.rejection(), "truncated");
+    }
+}
@@ -1,3 +1,17 @@
 pub mod csv;
 pub mod screen;
-pub mod cli;
\ No newline at end of file
+pub mod cli;
+pub mod index;
+pub mod popup;
+pub mod prompt;
+pub mod search;
+pub mod sort;
+pub mod filter;
+pub mod types;
+pub mod stats;
+pub mod frequency;
+pub mod detail;
+pub mod history;
+pub mod export;
+pub mod batch;
+pub mod print;
\ No newline at end of file
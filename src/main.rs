@@ -1,22 +1,64 @@
-use clap::Clap;
+use clap::{Clap, IntoApp};
 
-use hamstercsv::csv::*;
 use hamstercsv::screen::*;
 use hamstercsv::cli::*;
+use hamstercsv::filter::Filter;
+use hamstercsv::batch;
 
 use log;
 
 
 // TODO add `about(...)`s
-// TODO maybe stdin support
 
 
 fn main() {
+    let mut options = Options::parse();
+
+    // `--export` is short for the `convert` command.
+    let command = options.command.take()
+        .or_else(|| options.export.map(|to| Command::Convert(ConvertOptions { to, path: None })));
+    if let Some(path) = command.as_ref().and_then(|command| command.path()) {
+        options.path = Some(path.clone());
+    }
+
+    // Without a path the CSV comes from stdin, but nobody means to type it in by hand.
+    let stdin_is_terminal = unsafe { libc::isatty(libc::STDIN_FILENO) } == 1;
+    if options.path.is_none() && stdin_is_terminal {
+        eprintln!("error: No CSV file given, and stdin is a terminal.\n\n{}\n\nFor more information try --help",
+                  Options::into_app().generate_usage());
+        std::process::exit(2);
+    }
+
+    if let Some(command) = command {
+        std::process::exit(batch::run(&command, &options));
+    }
+
+    // Nobody could use the screen, as when piping into `less` or a file.
+    let stdout_is_terminal = unsafe { libc::isatty(libc::STDOUT_FILENO) } == 1;
+    if options.print || !stdout_is_terminal {
+        std::process::exit(batch::print(&options));
+    }
+
     simple_logging::log_to_file("hamstercsv.log", log::LevelFilter::Info).unwrap();
 
-    let options = Options::parse();
-    let csv = CSVFile::from(options.build_reader());
-    let mut display = CSVDisplay::from(csv, &options);    
+    let csv = options.open_csv().unwrap_or_else(|error| {
+        // Report before ncurses takes over the terminal.
+        eprintln!("{}", error);
+        std::process::exit(1)
+    });
+    let filter = options.filter.as_ref().map(|text| {
+        Filter::parse(text, &csv).unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            std::process::exit(1)
+        })
+    });
+    let mut display = CSVDisplay::from(csv, &options).unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        std::process::exit(1)
+    });
+    if let Some(filter) = filter {
+        display.set_filter(filter);
+    }
     display.run();
 
     // let column = csv.get_column(0).unwrap();
@@ -0,0 +1,169 @@
+use crate::csv::{display_width, MaleableUnicode, PADDING};
+
+static COLOR_POPUP_FOREGROUND: i16 = 28;
+static COLOR_POPUP_BACKGROUND: i16 = 29;
+static COLOR_POPUP_SELECTED_FOREGROUND: i16 = 30;
+static COLOR_POPUP_SELECTED_BACKGROUND: i16 = 31;
+
+static COLOR_POPUP_PAIR: i16 = 7;
+static COLOR_POPUP_SELECTED_PAIR: i16 = 8;
+
+pub fn initialize_colors() {
+    ncurses::init_color(COLOR_POPUP_FOREGROUND, 238 * 4, 232 * 4, 213 * 4);
+    ncurses::init_color(COLOR_POPUP_BACKGROUND, 7 * 4, 54 * 4, 66 * 4);
+    ncurses::init_pair (COLOR_POPUP_PAIR, COLOR_POPUP_FOREGROUND, COLOR_POPUP_BACKGROUND);
+
+    ncurses::init_color(COLOR_POPUP_SELECTED_FOREGROUND, 7 * 4, 54 * 4, 66 * 4);
+    ncurses::init_color(COLOR_POPUP_SELECTED_BACKGROUND, 181 * 4, 137 * 4, 0);
+    ncurses::init_pair (COLOR_POPUP_SELECTED_PAIR, COLOR_POPUP_SELECTED_FOREGROUND, COLOR_POPUP_SELECTED_BACKGROUND);
+}
+
+// A modal window drawn over the grid, showing a scrollable list of lines. If it is selectable, one
+// line is highlighted and can be picked with Enter.
+pub struct Popup {
+    title: String,
+    lines: Vec<String>,
+    selectable: bool,
+    selected: usize,
+    first_line: usize,
+    command_keys: Vec<char>, // Close the popup, so that the caller can act on them
+    pressed_key: Option<char>,
+}
+
+impl Popup {
+    pub fn new(title: String, lines: Vec<String>) -> Self {
+        Popup { title, lines, selectable: false, selected: 0, first_line: 0, command_keys: Vec::new(), pressed_key: None }
+    }
+
+    pub fn command_keys(mut self, keys: &str) -> Self {
+        self.command_keys = keys.chars().collect();
+        self
+    }
+
+    // The command key that closed the popup, if any.
+    pub fn pressed_key(&self) -> Option<char> {
+        self.pressed_key
+    }
+
+    pub fn selectable(mut self, selected: usize) -> Self {
+        self.selectable = true;
+        self.selected = std::cmp::min(selected, self.lines.len().saturating_sub(1));
+        self
+    }
+
+    fn dimensions(&self) -> (usize, usize) {
+        let mut screen_height: i32 = 0;
+        let mut screen_width: i32 = 0;
+        ncurses::getmaxyx(ncurses::stdscr(), &mut screen_height, &mut screen_width);
+
+        let content_width = self.lines.iter()
+            .map(|line| display_width(line))
+            .chain(std::iter::once(display_width(&self.title) + 2))
+            .max().unwrap_or(0);
+
+        let height = std::cmp::min(self.lines.len() + 2, (screen_height as usize).saturating_sub(2));
+        let width = std::cmp::min(content_width + 4, (screen_width as usize).saturating_sub(4));
+        (std::cmp::max(height, 3), std::cmp::max(width, 5))
+    }
+
+    fn draw(&self, window: ncurses::WINDOW, height: usize, width: usize) {
+        let inner_width = width - 4;
+        let inner_height = height - 2;
+
+        ncurses::wbkgd(window, ' ' as ncurses::chtype | ncurses::COLOR_PAIR(COLOR_POPUP_PAIR) as ncurses::chtype);
+        ncurses::werase(window);
+        ncurses::box_(window, 0, 0);
+
+        let title = format!(" {} ", self.title).cut_or_pad_to(inner_width, "").join("");
+        ncurses::mvwaddstr(window, 0, 2, title.as_str());
+
+        for (y, line_index) in (self.first_line..self.lines.len()).take(inner_height).enumerate() {
+            let text = self.lines[line_index].cut_or_pad_to(inner_width, PADDING).join("");
+            let selected = self.selectable && line_index == self.selected;
+            if selected {
+                ncurses::wattron(window, ncurses::COLOR_PAIR(COLOR_POPUP_SELECTED_PAIR));
+            }
+            ncurses::mvwaddstr(window, y as i32 + 1, 2, text.as_str());
+            if selected {
+                ncurses::wattroff(window, ncurses::COLOR_PAIR(COLOR_POPUP_SELECTED_PAIR));
+            }
+        }
+
+        ncurses::wrefresh(window);
+    }
+
+    fn scroll_to_selection(&mut self, inner_height: usize) {
+        if self.selected < self.first_line {
+            self.first_line = self.selected;
+        }
+        if self.selected >= self.first_line + inner_height {
+            self.first_line = self.selected + 1 - inner_height;
+        }
+    }
+
+    fn move_by(&mut self, delta: isize, inner_height: usize) {
+        let last_line = self.lines.len().saturating_sub(1);
+        if self.selectable {
+            self.selected = std::cmp::min((self.selected as isize + delta).max(0) as usize, last_line);
+            self.scroll_to_selection(inner_height);
+        } else {
+            let last_first_line = self.lines.len().saturating_sub(inner_height);
+            self.first_line = std::cmp::min((self.first_line as isize + delta).max(0) as usize, last_first_line);
+        }
+    }
+
+    // Shows the popup until it is dismissed with Escape or `q`. Returns the selected line, if the
+    // popup is selectable and Enter was pressed.
+    pub fn run(&mut self) -> Option<usize> {
+        let (height, width) = self.dimensions();
+        let mut screen_height: i32 = 0;
+        let mut screen_width: i32 = 0;
+        ncurses::getmaxyx(ncurses::stdscr(), &mut screen_height, &mut screen_width);
+
+        let y = (screen_height - height as i32) / 2;
+        let x = (screen_width - width as i32) / 2;
+        let window = ncurses::newwin(height as i32, width as i32, y.max(0), x.max(0));
+        if window.is_null() {
+            // The terminal is too small for even the smallest popup.
+            return None;
+        }
+        ncurses::keypad(window, true);
+
+        let inner_height = height - 2;
+        let page = inner_height as isize;
+        let result = loop {
+            self.draw(window, height, width);
+
+            match ncurses::wget_wch(window) {
+                Some(ncurses::WchResult::KeyCode(value)) => {
+                    match value.to_be_bytes() {
+                        [0, 0, 1, 2] => self.move_by(1, inner_height), // DOWN
+                        [0, 0, 1, 3] => self.move_by(-1, inner_height), // UP
+                        [0, 0, 1, 82] => self.move_by(page, inner_height), // PAGE DOWN
+                        [0, 0, 1, 83] => self.move_by(-page, inner_height), // PAGE UP
+                        [0, 0, 1, 6] => self.move_by(-(self.lines.len() as isize), inner_height), // HOME
+                        [0, 0, 1, 104] => self.move_by(self.lines.len() as isize, inner_height), // END
+                        [0, 0, 1, 87] if self.selectable => break Some(self.selected), // ENTER
+                        _ => (),
+                    }
+                }
+                Some(ncurses::WchResult::Char(value)) => {
+                    match std::char::from_u32(value) {
+                        Some('\n') | Some('\r') if self.selectable => break Some(self.selected),
+                        Some('\u{1b}') | Some('q') => break None,
+                        Some(character) if self.command_keys.contains(&character) => {
+                            self.pressed_key = Some(character);
+                            break None;
+                        }
+                        _ => (),
+                    }
+                }
+                None => (),
+            }
+        };
+
+        ncurses::delwin(window);
+        ncurses::touchwin(ncurses::stdscr());
+        result
+    }
+}
@@ -0,0 +1,55 @@
+use std::io::Write;
+
+use crate::csv::{align_right, fit_width, CSVColumn, CSVFile, CSVItem, CellDimentions, MaleableUnicode, PADDING};
+
+const HORIZONTAL: &str = "─";
+const VERTICAL: &str = "│";
+
+// Draws `rows` of every column as a grid with box-drawing borders, sized like the columns on
+// screen. Values that span lines are printed in full, so each row is as tall as its tallest value.
+pub fn write_table(csv: &CSVFile, rows: &[usize], max_column_width: usize, headers: bool, output: &mut dyn Write) -> std::io::Result<()> {
+    let columns: Vec<&CSVColumn> = (0..csv.column_count()).filter_map(|column_index| csv.get_column(column_index)).collect();
+    let widths: Vec<usize> = columns.iter()
+        .map(|column| fit_width(column.max_width(), std::cmp::max(max_column_width, 1)))
+        .collect();
+    let rule = |left: &str, middle: &str, right: &str| {
+        let segments: Vec<String> = widths.iter().map(|width| HORIZONTAL.repeat(width + 2)).collect();
+        format!("{}{}{}", left, segments.join(middle), right)
+    };
+    let write_line = |output: &mut dyn Write, cells: Vec<String>| {
+        writeln!(output, "{} {} {}", VERTICAL, cells.join(&format!(" {} ", VERTICAL)), VERTICAL)
+    };
+
+    writeln!(output, "{}", rule("┌", "┬", "┐"))?;
+    if headers {
+        let names = columns.iter().zip(widths.iter())
+            .map(|(column, &width)| column.header().to_owned().cut_or_pad_to(width, PADDING).join(""))
+            .collect();
+        write_line(output, names)?;
+        writeln!(output, "{}", rule("├", "┼", "┤"))?;
+    }
+
+    // Short rows have no value in some columns, but still need their cells drawn.
+    let missing = CSVItem::default();
+    for &row_index in rows {
+        let items: Vec<&CSVItem> = columns.iter().map(|column| column.value(row_index).unwrap_or(&missing)).collect();
+        let height = std::cmp::max(items.iter().map(|item| item.height()).max().unwrap_or(0), 1);
+        let cells: Vec<Vec<String>> = items.iter().zip(columns.iter()).zip(widths.iter())
+            .map(|((item, column), &width)| {
+                item.cut_or_pad_to(CellDimentions { width, height }, PADDING)
+                    .into_iter()
+                    .map(|mut line| {
+                        if column.column_type().is_numeric() {
+                            align_right(&mut line);
+                        }
+                        line.join("")
+                    })
+                    .collect()
+            })
+            .collect();
+        for line_index in 0..height {
+            write_line(output, cells.iter().map(|cell| cell[line_index].clone()).collect())?;
+        }
+    }
+    writeln!(output, "{}", rule("└", "┴", "┘"))
+}
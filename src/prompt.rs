@@ -0,0 +1,98 @@
+use crate::csv::{display_width, MaleableUnicode, PADDING};
+
+pub enum Edit {
+    Changed,
+    Unchanged,
+    Submit,
+    Cancel,
+    Control(char), // Control characters that are not used for editing, left for the caller to interpret
+}
+
+// A single line of editable text, drawn in the status bar.
+#[derive(Debug, Default)]
+pub struct LineEditor {
+    text: Vec<char>,
+    cursor: usize, // Invariant: <= text.len()
+}
+
+impl LineEditor {
+    pub fn new(text: &str) -> Self {
+        let text: Vec<char> = text.chars().collect();
+        LineEditor { cursor: text.len(), text }
+    }
+
+    pub fn text(&self) -> String {
+        self.text.iter().collect()
+    }
+
+    pub fn handle(&mut self, input: ncurses::WchResult) -> Edit {
+        match input {
+            ncurses::WchResult::KeyCode(value) => {
+                match value.to_be_bytes() {
+                    [0, 0, 1, 4] => { self.cursor = self.cursor.saturating_sub(1); Edit::Unchanged } // LEFT
+                    [0, 0, 1, 5] => { self.cursor = std::cmp::min(self.cursor + 1, self.text.len()); Edit::Unchanged } // RIGHT
+                    [0, 0, 1, 6] => { self.cursor = 0; Edit::Unchanged } // HOME
+                    [0, 0, 1, 104] => { self.cursor = self.text.len(); Edit::Unchanged } // END
+                    [0, 0, 1, 7] => self.delete_before_cursor(), // BACKSPACE
+                    [0, 0, 1, 74] => self.delete_at_cursor(), // DELETE
+                    [0, 0, 1, 87] => Edit::Submit, // ENTER
+                    _ => Edit::Unchanged,
+                }
+            }
+            ncurses::WchResult::Char(value) => {
+                match std::char::from_u32(value) {
+                    Some('\n') | Some('\r') => Edit::Submit,
+                    Some('\u{1b}') => Edit::Cancel,
+                    Some('\u{7f}') | Some('\u{8}') => self.delete_before_cursor(),
+                    Some(character) if character.is_control() => Edit::Control(character),
+                    Some(character) => {
+                        self.text.insert(self.cursor, character);
+                        self.cursor += 1;
+                        Edit::Changed
+                    }
+                    None => Edit::Unchanged,
+                }
+            }
+        }
+    }
+
+    fn delete_before_cursor(&mut self) -> Edit {
+        if self.cursor == 0 {
+            return Edit::Unchanged;
+        }
+        self.cursor -= 1;
+        self.text.remove(self.cursor);
+        Edit::Changed
+    }
+
+    fn delete_at_cursor(&mut self) -> Edit {
+        if self.cursor >= self.text.len() {
+            return Edit::Unchanged;
+        }
+        self.text.remove(self.cursor);
+        Edit::Changed
+    }
+
+    // Draws `prefix`, the text and `suffix` on line `y`, and leaves the terminal cursor at the
+    // editing position. Long text is scrolled so that the editing position stays visible.
+    pub fn draw(&self, y: i32, prefix: &str, suffix: &str, width: usize) {
+        let prefix_width = display_width(prefix);
+        let suffix_width = display_width(suffix);
+        let text_width = width.saturating_sub(prefix_width + suffix_width + 1).max(1);
+
+        let width_of = |characters: &[char]| display_width(&characters.iter().collect::<String>());
+        let mut first_visible = self.cursor;
+        while first_visible > 0 && width_of(&self.text[first_visible - 1..self.cursor]) < text_width {
+            first_visible -= 1;
+        }
+        let visible: String = self.text[first_visible..].iter().collect();
+        let visible = visible.cut_or_pad_to(text_width, PADDING).join("");
+
+        ncurses::mv(y, 0);
+        ncurses::clrtoeol();
+        ncurses::addstr(prefix);
+        ncurses::addstr(visible.as_str());
+        ncurses::addstr(suffix);
+        ncurses::mv(y, (prefix_width + width_of(&self.text[first_visible..self.cursor])) as i32);
+    }
+}
@@ -1,4 +1,19 @@
-use crate::{cli::Options, csv::*};
+use std::collections::{BTreeSet, HashMap};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{cli::{Dialect, Options}, csv::*, popup::{self, Popup}};
+use crate::prompt::{Edit, LineEditor};
+use crate::search::{Direction, Search, SearchOptions};
+use crate::sort::{self, SortKey, SortOrder};
+use crate::filter::Filter;
+use crate::stats::ColumnStatistics;
+use crate::frequency;
+use crate::detail;
+use crate::history::{Change, History};
+use crate::export::{Format, Table};
 
 static COLOR_FOREGROUND: i16 = 24;
 static COLOR_BACKGROUND: i16 = 25;
@@ -15,40 +30,107 @@ static COLOR_VALUES_FOREGROUND_ODD: i16 = 21;
 static COLOR_HEADER_FOREGROUND_ODD: i16 = 22;
 static COLOR_HEADER_BACKGROUND_ODD: i16 = 23;
 
+static COLOR_ERROR_FOREGROUND: i16 = 26;
+static COLOR_ERROR_BACKGROUND: i16 = 27;
+
+static COLOR_CURSOR_FOREGROUND: i16 = 32;
+static COLOR_CURSOR_BACKGROUND: i16 = 33;
+
+static COLOR_MATCH_FOREGROUND: i16 = 34;
+static COLOR_MATCH_BACKGROUND: i16 = 35;
+
 static COLOR_PAIR: i16 = 1;
 static COLOR_VALUES_PAIR_EVEN: i16 = 2;
 static COLOR_HEADER_PAIR_EVEN: i16 = 3;
 static COLOR_VALUES_PAIR_ODD: i16 = 4;
 static COLOR_HEADER_PAIR_ODD: i16 = 5;
+static COLOR_ERROR_PAIR: i16 = 6;
+static COLOR_CURSOR_PAIR: i16 = 9;
+static COLOR_MATCH_PAIR: i16 = 10;
+
+#[derive(Debug)]
+pub struct TerminalError(String);
+impl std::fmt::Display for TerminalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        write!(f, "Cannot use /dev/tty for keyboard input while reading CSV from stdin: {}.", self.0)
+    }
+}
+
+// Enough for the headers, one row of one (narrow) column and the status bar.
+const MIN_SCREEN_HEIGHT: usize = 3;
+const MIN_SCREEN_WIDTH: usize = 10;
+
+// Rows loaded between progress updates when the whole file has to be loaded.
+const LOAD_STEP: usize = 10000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RowNumbers {
+    Hidden,
+    Records,
+    RecordsAndLines, // Lines in the file differ from records once values span several lines
+}
+
+enum ColumnWidth {
+    Fixed(usize),
+    Fit, // As wide as the widest value, ignoring `max_column_width`
+}
 
 pub struct CSVDisplay {
-    first_column: usize,
+    first_column: usize, // First column to the right of the frozen ones, invariant: >= frozen columns shown
     last_column: usize, // Invariant last_column >= first_column
+    frozen_columns: usize, // Always shown on the left, unless they do not fit
+    row_numbers: RowNumbers, // Shown in a gutter left of the columns
 
     first_row: usize,
     last_row: usize, // Invariant last_row >= first_row
 
+    cursor_row: usize,    // Invariant: < row count, unless there are no rows
+    cursor_column: usize, // Invariant: < column count, unless there are no columns
+
     visible_columns: usize,
     visible_rows: usize,
 
-    column_width: usize, // Invariant: > 0 & <= screen_width
+    max_column_width: usize, // Invariant: > 0
+    column_layout: Vec<(usize, usize, usize)>, // Column index, x and width of the frozen columns and then first_column..last_column
+    column_widths: HashMap<usize, ColumnWidth>, // Widths set by the user, overriding automatic sizing
+    hidden_columns: BTreeSet<usize>, // Left out of the grid, the record view and exports. Invariant: not all of them
     row_height: usize,   // Invariant: > 0 & <= screen_height - 2
 
     screen_height: usize, 
     screen_width: usize,
 
-    cell_dimensions: CellDimentions,
-    
+    sort_keys: Vec<SortKey>,
+    row_order: Option<Vec<usize>>, // Rows in display order, if that is not the order of the file
+    filter: Option<Filter>,
+
+    search: Option<Search>,
+    search_options: SearchOptions,
+    search_counter: String,
+
+    message: Option<String>, // Shown in the status bar until the next key press
+
+    derived: bool, // Shows a table computed from another display's file, and runs inside that display
+
+    record_view: bool, // Shows the record under the cursor with one field per line, instead of the grid
+    record_first_field: usize, // Column shown at the top of the record view
+
+    save_path: Option<PathBuf>, // Where edits are saved, unless the file came from stdin
+    dialect: Dialect, // The file was read with
+    headers: bool,
+    history: History, // Edits and sorts, to be undone and redone
+    save_altered: bool, // Agreed to save values that were trimmed or had invalid UTF-8 when read
+
     csv: CSVFile,    
 }
 impl CSVDisplay {
-    pub fn from(csv: CSVFile, options: &Options) -> Self {
+    pub fn from(csv: CSVFile, options: &Options) -> Result<Self, TerminalError> {
 
         ncurses::setlocale(ncurses::LcCategory::all, options.locale.as_str()); // TODO is this actually configurable to any reasonable extent?
 
-        ncurses::initscr();
+        CSVDisplay::initialize_terminal(options)?;
         ncurses::keypad(ncurses::stdscr(), true);
         ncurses::noecho();
+        ncurses::set_escdelay(25);
 
         ncurses::start_color();
 
@@ -71,6 +153,20 @@ impl CSVDisplay {
         ncurses::init_color(COLOR_VALUES_BACKGROUND_ODD, 0, 43 * 4, 54 * 4);
         ncurses::init_color(COLOR_VALUES_FOREGROUND_ODD, 142 * 4, 161 * 4, 161 * 4);    
         ncurses::init_pair (COLOR_VALUES_PAIR_ODD, COLOR_VALUES_FOREGROUND_ODD, COLOR_VALUES_BACKGROUND_ODD);
+
+        ncurses::init_color(COLOR_ERROR_FOREGROUND, 253 * 4, 246 * 4, 227 * 4);
+        ncurses::init_color(COLOR_ERROR_BACKGROUND, 220 * 4, 50 * 4, 47 * 4);
+        ncurses::init_pair (COLOR_ERROR_PAIR, COLOR_ERROR_FOREGROUND, COLOR_ERROR_BACKGROUND);
+
+        ncurses::init_color(COLOR_CURSOR_FOREGROUND, 7 * 4, 54 * 4, 66 * 4);
+        ncurses::init_color(COLOR_CURSOR_BACKGROUND, 42 * 4, 161 * 4, 152 * 4);
+        ncurses::init_pair (COLOR_CURSOR_PAIR, COLOR_CURSOR_FOREGROUND, COLOR_CURSOR_BACKGROUND);
+
+        ncurses::init_color(COLOR_MATCH_FOREGROUND, 7 * 4, 54 * 4, 66 * 4);
+        ncurses::init_color(COLOR_MATCH_BACKGROUND, 181 * 4, 137 * 4, 0);
+        ncurses::init_pair (COLOR_MATCH_PAIR, COLOR_MATCH_FOREGROUND, COLOR_MATCH_BACKGROUND);
+
+        popup::initialize_colors();
         
         ncurses::bkgd(' ' as ncurses::chtype | ncurses::COLOR_PAIR(COLOR_PAIR) as ncurses::chtype);
 
@@ -78,31 +174,99 @@ impl CSVDisplay {
 
         ncurses::clear();
 
+        let mut display = CSVDisplay::new(csv, options.max_column_width, false);
+        display.save_path = if options.reads_from_stdin() { None } else { options.path.clone() };
+        display.dialect = options.dialect();
+        display.headers = !options.no_headers;
+        display.frozen_columns = options.frozen_columns;
+        display.row_numbers = if options.line_numbers {
+            RowNumbers::RecordsAndLines
+        } else if options.row_numbers {
+            RowNumbers::Records
+        } else {
+            RowNumbers::Hidden
+        };
+        Ok(display)
+    }
+
+    // The terminal has to be set up already.
+    fn new(csv: CSVFile, max_column_width: usize, derived: bool) -> Self {
         let mut display = CSVDisplay { 
             csv, 
 
             first_row: 0, 
             first_column: 0, 
 
+            cursor_row: 0,
+            cursor_column: 0,
+
             last_row: 0, 
             last_column: 0, 
+            frozen_columns: 0,
+            row_numbers: RowNumbers::Hidden,
 
             visible_columns: 0,
             visible_rows: 0,
 
-            column_width: 10, 
+            max_column_width: std::cmp::max(max_column_width, 1),
+            column_layout: Vec::new(),
+            column_widths: HashMap::new(),
+            hidden_columns: BTreeSet::new(),
             row_height: 2, 
 
             screen_height: 0,
             screen_width: 0,
 
-            cell_dimensions: CellDimentions { height: 0, width: 0 }
+            sort_keys: Vec::new(),
+            row_order: None,
+            filter: None,
+
+            search: None,
+            search_options: SearchOptions::default(),
+            search_counter: String::new(),
+
+            message: None,
+
+            derived,
+
+            record_view: false,
+            record_first_field: 0,
+
+            save_path: None,
+            dialect: Dialect::default(),
+            headers: true,
+            history: History::default(),
+            save_altered: false,
         };
 
         display.measure_screen();
         display
     }
 
+    fn initialize_terminal(options: &Options) -> Result<(), TerminalError> {
+        let stdin_is_terminal = unsafe { libc::isatty(libc::STDIN_FILENO) } == 1;
+        if !options.reads_from_stdin() || stdin_is_terminal {
+            ncurses::initscr();
+            return Ok(());
+        }
+
+        // The CSV data arrives through stdin, so keyboard input has to come from the controlling terminal,
+        // which there is none of under cron or in CI, for example.
+        let tty_path = std::ffi::CString::new("/dev/tty").unwrap();
+        let tty_mode = std::ffi::CString::new("r+").unwrap();
+        let tty = unsafe { libc::fopen(tty_path.as_ptr(), tty_mode.as_ptr()) };
+        if tty.is_null() {
+            return Err(TerminalError(std::io::Error::last_os_error().to_string()));
+        }
+        let screen = ncurses::newterm(None, tty, tty);
+        if screen.is_null() {
+            unsafe { libc::fclose(tty) };
+            return Err(TerminalError("the terminal cannot be set up".to_owned()));
+        }
+        ncurses::set_term(screen);
+        Ok(())
+    }
+
     fn measure_screen(&mut self) -> bool {
         let mut screen_height: i32 = 0;
         let mut screen_width: i32 = 0;
@@ -119,36 +283,310 @@ impl CSVDisplay {
     }
 
     fn figure_out_which_rows_to_display(&mut self) -> () {
-        self.visible_rows = self.screen_height / self.row_height - 1 /* headers */ - 1 /* status bar */; 
-        self.last_row = std::cmp::min(self.first_row + self.visible_rows, self.csv.row_count());
+        self.visible_rows = self.screen_height.saturating_sub(1 /* headers */ + 1 /* status bar */) / self.row_height; 
+
+        // Keep the cursor on screen.
+        if self.cursor_row < self.first_row {
+            self.first_row = self.cursor_row;
+        }
+        if self.cursor_row >= self.first_row + self.visible_rows {
+            self.first_row = self.cursor_row + 1 - std::cmp::max(self.visible_rows, 1);
+        }
+
+        // One extra row, so that we know whether scrolling down is possible.
+        self.ensure_rows_loaded(self.first_row..self.first_row + self.visible_rows + 1);
+        self.last_row = std::cmp::min(self.first_row + self.visible_rows, self.row_count());
 
         log::info!("Displaying rows: {}..{} (total: {} rows)", 
-                   self.first_row, self.last_row, self.csv.row_count());
+                   self.first_row, self.last_row, self.row_count());
+    }
+
+    fn column_content_width(&self, column_index: usize) -> usize {
+        let sort_marker = if self.sort_keys.iter().any(|key| key.column == column_index) { 1 } else { 0 };
+        let content_width = self.csv.get_column(column_index).map_or(0, |column| column.max_width()) + sort_marker;
+        let content_width = std::cmp::max(content_width, 1);
+        let width = match self.column_widths.get(&column_index) {
+            Some(ColumnWidth::Fixed(width)) => *width,
+            Some(ColumnWidth::Fit) => content_width,
+            None => fit_width(content_width, self.max_column_width),
+        };
+        std::cmp::min(width, std::cmp::max(self.screen_width, 2) - 1)
+    }
+
+    // Row positions in the display and row indices in the file only differ once rows are sorted or
+    // filtered. A filtered view can be empty, so there may be no row at all.
+    fn source_row(&self, row: usize) -> Option<usize> {
+        match &self.row_order {
+            Some(order) => order.get(row).cloned(),
+            None => Some(row),
+        }
+    }
+
+    fn display_row_of(&self, row_index: usize) -> Option<usize> {
+        match &self.row_order {
+            Some(order) => order.iter().position(|&source_row| source_row == row_index),
+            None => Some(row_index),
+        }
+    }
+
+    fn row_count(&self) -> usize {
+        self.row_order.as_ref().map_or(self.csv.row_count(), |order| order.len())
+    }
+
+    // A reordered or filtered view already has every row loaded.
+    fn ensure_rows_loaded(&mut self, rows: std::ops::Range<usize>) {
+        if self.row_order.is_none() {
+            self.csv.ensure_loaded(rows);
+        }
+    }
+
+    fn count_all_rows(&mut self) -> usize {
+        match &self.row_order {
+            Some(order) => order.len(),
+            None => self.csv.count_all_rows(),
+        }
+    }
+
+    // Includes the one character gap separating the column from the next one.
+    fn column_width(&self, column_index: usize) -> usize {
+        if self.is_hidden(column_index) {
+            return 0;
+        }
+        self.column_content_width(column_index) + 1
+    }
+
+    fn is_hidden(&self, column_index: usize) -> bool {
+        self.hidden_columns.contains(&column_index)
+    }
+
+    fn hide_column(&mut self) {
+        let shown = self.csv.column_count() - self.hidden_columns.len();
+        if shown <= 1 || self.cursor_column >= self.csv.column_count() {
+            self.message = Some("Cannot hide the only column shown".to_owned());
+            return;
+        }
+        let column_index = self.cursor_column;
+        self.hidden_columns.insert(column_index);
+        self.move_cursor_right_by(1);
+        if self.cursor_column == column_index {
+            self.move_cursor_left_by(1);
+        }
+        self.message = Some(format!("Hidden {} (v shows all columns)", self.csv.column_name(column_index)));
+    }
+
+    fn show_all_columns(&mut self) {
+        self.hidden_columns.clear();
+    }
+
+    fn widen_column(&mut self, column_index: usize) {
+        let width = self.column_content_width(column_index) + 1;
+        self.column_widths.insert(column_index, ColumnWidth::Fixed(width));
+    }
+
+    fn narrow_column(&mut self, column_index: usize) {
+        let width = std::cmp::max(self.column_content_width(column_index) - 1, 1);
+        self.column_widths.insert(column_index, ColumnWidth::Fixed(width));
+    }
+
+    fn fit_all_columns(&mut self) {
+        for column_index in 0..self.csv.column_count() {
+            self.column_widths.insert(column_index, ColumnWidth::Fit);
+        }
+    }
+
+    fn increase_row_height(&mut self) {
+        self.row_height = std::cmp::min(self.row_height + 1, std::cmp::max(self.screen_height.saturating_sub(2), 1));
+    }
+
+    fn decrease_row_height(&mut self) {
+        self.row_height = std::cmp::max(self.row_height - 1, 1);
+    }
+
+    // Frozen columns that fit on the screen next to at least one more column, and the width they
+    // take up with the separator after them.
+    fn frozen_columns_shown(&self) -> (usize, usize) {
+        let frozen = std::cmp::min(self.frozen_columns, self.csv.column_count());
+        if frozen == 0 {
+            return (0, 0);
+        }
+        let width = (0..frozen).map(|column_index| self.column_width(column_index)).sum::<usize>() + 1;
+        if self.gutter_width() + width >= self.screen_width {
+            return (0, 0);
+        }
+        (frozen, width)
+    }
+
+    fn digits(number: usize) -> usize {
+        number.to_string().len()
+    }
+
+    // Widths of the record and line numbers in the gutter, without the spaces after them.
+    fn gutter_number_widths(&self) -> (usize, usize) {
+        let row_count = self.csv.row_count();
+        let records = Self::digits(row_count);
+        let lines = row_count.checked_sub(1)
+            .and_then(|last_row| self.csv.row_offset(last_row))
+            .map_or(records, |offset| std::cmp::max(Self::digits(offset.line as usize), records));
+        (records, std::cmp::max(lines, "line".len()))
+    }
+
+    fn gutter_width(&self) -> usize {
+        let (records, lines) = self.gutter_number_widths();
+        match self.row_numbers {
+            RowNumbers::Hidden => 0,
+            RowNumbers::Records => records + 1,
+            RowNumbers::RecordsAndLines => records + 1 + lines + 1,
+        }
+    }
+
+    fn cycle_row_numbers(&mut self) {
+        self.row_numbers = match self.row_numbers {
+            RowNumbers::Hidden => RowNumbers::Records,
+            RowNumbers::Records => RowNumbers::RecordsAndLines,
+            RowNumbers::RecordsAndLines => RowNumbers::Hidden,
+        };
+    }
+
+    // Record numbers are those in the file, even when the rows are sorted or filtered.
+    fn display_gutter(&self) {
+        if self.row_numbers == RowNumbers::Hidden {
+            return;
+        }
+        let (records, lines) = self.gutter_number_widths();
+        let with_lines = self.row_numbers == RowNumbers::RecordsAndLines;
+
+        ncurses::attron(ncurses::COLOR_PAIR(COLOR_HEADER_PAIR_EVEN));
+        ncurses::mv(0, 0);
+        let header = if with_lines {
+            format!("{:>records$} {:>lines$} ", "#", "line", records = records, lines = lines)
+        } else {
+            format!("{:>records$} ", "#", records = records)
+        };
+        ncurses::addstr(header.cut_or_pad_to(self.gutter_width(), PADDING).join("").as_str());
+
+        for (y, row) in (self.first_row..self.last_row).enumerate() {
+            let row_index = match self.source_row(row) {
+                Some(row_index) => row_index,
+                None => continue,
+            };
+            let number = if with_lines {
+                let line = self.csv.row_offset(row_index).map_or_else(String::new, |offset| offset.line.to_string());
+                format!("{:>records$} {:>lines$} ", row_index + 1, line, records = records, lines = lines)
+            } else {
+                format!("{:>records$} ", row_index + 1, records = records)
+            };
+            if row == self.cursor_row {
+                ncurses::attron(ncurses::A_BOLD());
+            }
+            ncurses::mv((1 + y * self.row_height) as i32, 0);
+            ncurses::addstr(number.as_str());
+            ncurses::attroff(ncurses::A_BOLD());
+        }
+        ncurses::attroff(ncurses::COLOR_PAIR(COLOR_HEADER_PAIR_EVEN));
+    }
+
+    fn toggle_frozen_columns(&mut self) {
+        self.frozen_columns = if self.frozen_columns == self.cursor_column + 1 { 0 } else { self.cursor_column + 1 };
     }
 
     fn figure_out_which_columns_to_display(&mut self) -> () {
-        self.visible_columns = self.screen_width / self.column_width;
-        self.last_column = self.first_column + self.visible_columns;
+        let (frozen, frozen_width) = self.frozen_columns_shown();
+        let gutter_width = self.gutter_width();
+        let scrolling_width = self.screen_width.saturating_sub(gutter_width + frozen_width);
+        self.first_column = std::cmp::max(self.first_column, frozen);
+
+        // Keep the cursor on screen, if it is not in a frozen column.
+        if self.cursor_column >= frozen {
+            if self.cursor_column < self.first_column {
+                self.first_column = self.cursor_column;
+            }
+            while self.first_column < self.cursor_column 
+                && (self.first_column..=self.cursor_column).map(|column_index| self.column_width(column_index)).sum::<usize>() > scrolling_width {
+                self.first_column += 1;
+            }
+        }
+
+        self.column_layout.clear();
+        let mut x = gutter_width;
+        for column_index in 0..frozen {
+            if self.is_hidden(column_index) {
+                continue;
+            }
+            let width = self.column_width(column_index);
+            self.column_layout.push((column_index, x, width));
+            x += width;
+        }
+
+        // Pack as many whole columns as fit, but always at least one.
+        let mut x = gutter_width + frozen_width;
+        let mut column_index = self.first_column;
+        while column_index < self.csv.column_count() {
+            if self.is_hidden(column_index) {
+                column_index += 1;
+                continue;
+            }
+            let width = self.column_width(column_index);
+            if x + width > self.screen_width && x > gutter_width + frozen_width {
+                break;
+            }
+            self.column_layout.push((column_index, x, width));
+            x += width;
+            column_index += 1;
+        }
+
+        self.last_column = column_index;
+        self.visible_columns = self.last_column - self.first_column;
 
         log::info!("Displaying columns: {}..{} (total: {} columns", 
                    self.first_column, self.last_column, self.csv.column_count());
     }
 
-    fn figure_out_cell_dimensions(&mut self) -> () {
-        self.cell_dimensions = CellDimentions { width: self.column_width - 1, height: self.row_height };
+    // Where the column is drawn, if it is.
+    fn column_position(&self, column_index: usize) -> Option<(usize, usize)> {
+        self.column_layout.iter()
+            .find(|(laid_out, _, _)| *laid_out == column_index)
+            .map(|(_, x, width)| (*x, *width))
+    }
+
+    fn cell_dimensions(&self, column_index: usize) -> CellDimentions {
+        let (_, width) = self.column_position(column_index).unwrap_or((0, 1));
+        CellDimentions { width: width - 1, height: self.row_height }
+    }
+
+    fn display_frozen_separator(&self) {
+        let (frozen, frozen_width) = self.frozen_columns_shown();
+        if frozen == 0 {
+            return;
+        }
+        ncurses::attron(ncurses::COLOR_PAIR(COLOR_PAIR));
+        ncurses::mvvline(0, (self.gutter_width() + frozen_width) as i32 - 1, ncurses::ACS_VLINE(), self.screen_height.saturating_sub(1) as i32);
+        ncurses::attroff(ncurses::COLOR_PAIR(COLOR_PAIR));
     }
 
     fn display_column_header(&self, column_index: usize, column: &CSVColumn) {       
 
-        let text = column.header().to_owned()
-            .cut_or_pad_to(self.cell_dimensions.width, " ")
+        let sort_key = self.sort_keys.iter().find(|key| key.column == column_index);
+        let header = match sort_key {
+            Some(key) => format!("{}{}", key.arrow(), column.header()),
+            None => column.header().to_owned(),
+        };
+
+        let text = header
+            .cut_or_pad_to(self.cell_dimensions(column_index).width, " ")
             .join("");
 
-        let x = ((column_index - self.first_column) * self.column_width) as i32;
+        let (x, _) = self.column_position(column_index).unwrap_or((0, 1));
+        let x = x as i32;
         let y = 0;
 
         let is_even = column_index % 2 == 0;
-        let colors = if is_even { COLOR_HEADER_PAIR_EVEN } else { COLOR_HEADER_PAIR_ODD };
+        let colors = if column_index == self.cursor_column { 
+            COLOR_CURSOR_PAIR 
+        } else if is_even { 
+            COLOR_HEADER_PAIR_EVEN 
+        } else { 
+            COLOR_HEADER_PAIR_ODD 
+        };
 
         log::info!("display header, column_index: {}, color: {}", column_index, colors);
 
@@ -166,23 +604,44 @@ impl CSVDisplay {
         let is_even = column_index % 2 == 0;
         let colors = if is_even { COLOR_VALUES_PAIR_EVEN } else { COLOR_VALUES_PAIR_ODD };
 
+        // Short rows have no value in this column, but still need their cell drawn.
+        let missing = CSVItem::default();
+        let cell_dimensions = self.cell_dimensions(column_index);
+        let (x, _) = self.column_position(column_index).unwrap_or((0, 1));
+        let x = x as i32;
+        let align_right = column.column_type().is_numeric();
+
         let cells = (self.first_row..self.last_row)
-            .map(|row_index| {
-                column.value(row_index)
-                    .map_or_else(Vec::new, |csv_item| {
-                        csv_item.cut_or_pad_to(self.cell_dimensions, PADDING)
-                            .into_iter()
-                            .map(|vector| {
-                                vector.join("")
-                            }).collect()
-                    })                    
+            .filter_map(|row| {
+                let row_index = self.source_row(row)?;
+                let csv_item = column.value(row_index).unwrap_or(&missing);
+                let lines: Vec<String> = csv_item.cut_or_pad_to(cell_dimensions, PADDING)
+                    .into_iter()
+                    .map(|mut vector| {
+                        if align_right {
+                            crate::csv::align_right(&mut vector);
+                        }
+                        vector.join("")
+                    }).collect();
+                Some((row, row_index, lines))
             });
 
-        ncurses::attron(ncurses::COLOR_PAIR(colors));
-        
         let mut y = 1;                
-        for row_lines in cells {
-            let x = ((column_index - self.first_column) * self.column_width) as i32;
+        for (row, row_index, row_lines) in cells {
+
+            let is_match = self.search.as_ref()
+                .is_some_and(|search| column.value(row_index).is_some_and(|item| search.matches(item)));
+
+            let colors = if row == self.cursor_row && column_index == self.cursor_column {
+                COLOR_CURSOR_PAIR
+            } else if is_match {
+                COLOR_MATCH_PAIR
+            } else if self.csv.row_error(row_index).is_some() { 
+                COLOR_ERROR_PAIR 
+            } else { 
+                colors 
+            };
+            ncurses::attron(ncurses::COLOR_PAIR(colors));
 
             for row_line in row_lines {  
                 ncurses::mv(y, x);
@@ -191,45 +650,1170 @@ impl CSVDisplay {
 
                 y += 1;
             }
+
+            ncurses::attroff(ncurses::COLOR_PAIR(colors));
         } 
+    }
 
-        ncurses::attroff(ncurses::COLOR_PAIR(colors));
+    fn show_errors(&mut self) {
+        let errors: Vec<&RowError> = self.csv.errors().collect();
+        if errors.is_empty() {
+            return;
+        }
+
+        let title = if self.csv.is_fully_loaded() { 
+            format!("Malformed rows: {}", errors.len()) 
+        } else { 
+            format!("Malformed rows: {} (in rows loaded so far)", errors.len()) 
+        };
+        let lines = errors.iter().map(|error| error.to_string()).collect();
+        let cursor_row_index = self.source_row(self.cursor_row).unwrap_or(0);
+        let current = errors.iter().position(|error| error.row >= cursor_row_index).unwrap_or(0);
+        let rows: Vec<usize> = errors.iter().map(|error| error.row).collect();
+
+        if let Some(selected) = Popup::new(title, lines).selectable(current).run() {
+            match self.display_row_of(rows[selected]) {
+                Some(row) => self.cursor_row = row,
+                None => self.message = Some(format!("Row {} is not shown", rows[selected] + 1)),
+            }
+        }
     }
 
-    pub fn run(&mut self) {
+    // Works on the rows shown, which means loading the whole file first. Escape cancels the loading
+    // and the computation, both of which can take a while.
+    fn show_statistics(&mut self) {
+        if self.cursor_column >= self.csv.column_count() {
+            return;
+        }
+        if !self.load_all_rows("Loading the whole file for statistics") {
+            self.message = Some("Statistics cancelled".to_owned());
+            return;
+        }
+        let rows: Vec<usize> = match &self.row_order {
+            Some(order) => order.clone(),
+            None => (0..self.csv.row_count()).collect(),
+        };
+        let column = match self.csv.get_column(self.cursor_column) {
+            Some(column) => column,
+            None => return,
+        };
+        let header = match column.header() {
+            "" => format!("column {}", self.cursor_column + 1),
+            header => header.to_owned(),
+        };
+
+        let status_y = self.screen_height as i32 - 1;
+        let status_width = self.screen_width.saturating_sub(1);
+        ncurses::nodelay(ncurses::stdscr(), true);
+        let statistics = ColumnStatistics::compute(column, &rows, |done, total| {
+            let status = format!("Computing statistics of {}: {}% (Esc to cancel)", header, done * 100 / std::cmp::max(total, 1));
+            ncurses::mv(status_y, 0);
+            ncurses::addstr(status.cut_or_pad_to(status_width, PADDING).join("").as_str());
+            ncurses::refresh();
+            !matches!(ncurses::get_wch(), Some(ncurses::WchResult::Char(0x1b)))
+        });
+        ncurses::nodelay(ncurses::stdscr(), false);
+
+        match statistics {
+            Some(statistics) => {
+                let title = if self.filter.is_some() {
+                    format!("Statistics: {} (in {} of {} rows)", header, rows.len(), self.csv.row_count())
+                } else {
+                    format!("Statistics: {}", header)
+                };
+                Popup::new(title, statistics.lines()).run();
+            }
+            None => self.message = Some("Statistics cancelled".to_owned()),
+        }
+    }
+
+    // Shows the whole value of the cell under the cursor, wrapped to fit. JSON and XML values can be
+    // pretty-printed with `p`.
+    fn show_cell(&mut self) {
+        let row_index = match self.source_row(self.cursor_row) {
+            Some(row_index) => row_index,
+            None => return,
+        };
+        let text = match self.csv.get_column(self.cursor_column).and_then(|column| column.value(row_index)) {
+            Some(item) => item.text(),
+            None => String::new(),
+        };
+        let pretty = detail::pretty(&text);
+        let wrap_width = std::cmp::max(self.screen_width.saturating_sub(8), 10);
+        let title = format!("{}, row {}", self.csv.column_name(self.cursor_column), row_index + 1);
 
+        let mut show_pretty = false;
         loop {
+            let mut lines = vec![
+                format!("row {}, column {}", row_index + 1, self.cursor_column + 1),
+                format!("{} bytes, {} graphemes", text.len(), text.graphemes(true).count()),
+            ];
+            if pretty.is_some() {
+                lines.push(if show_pretty { "p: show as is".to_owned() } else { "p: pretty-print".to_owned() });
+            }
+            lines.push(String::new());
+            let value = match &pretty {
+                Some(pretty) if show_pretty => pretty,
+                _ => &text,
+            };
+            lines.extend(detail::wrap(value, wrap_width));
+
+            // Clear away the previous popup.
+            self.display();
+            ncurses::refresh();
+            let mut popup = Popup::new(title.clone(), lines).command_keys("p");
+            popup.run();
+            match popup.pressed_key() {
+                Some('p') if pretty.is_some() => show_pretty = !show_pretty,
+                Some('p') => (),
+                _ => break,
+            }
+        }
+    }
+
+    // Replaces the grid with a table of the values in the current column and their counts, among the
+    // rows shown. Picking a value there filters the rows down to those with that value.
+    fn show_frequencies(&mut self, aggregate: Option<usize>) {
+        if self.cursor_column >= self.csv.column_count() {
+            return;
+        }
+        if !self.load_all_rows("Loading the whole file for frequencies") {
+            self.message = Some("Frequencies cancelled".to_owned());
+            return;
+        }
+        let rows: Vec<usize> = match &self.row_order {
+            Some(order) => order.clone(),
+            None => (0..self.csv.row_count()).collect(),
+        };
+        let table = frequency::frequency_table(&self.csv, &rows, self.cursor_column, aggregate);
+
+        let mut view = CSVDisplay::new(table, self.max_column_width, true);
+        view.message = Some("Enter shows the rows with a value, q goes back".to_owned());
+        let value = view.run_view()
+            .and_then(|row_index| view.csv.get_column(0)?.value(row_index).map(|item| item.text()));
+        if let Some(value) = value {
+            self.filter_by_value(self.cursor_column, &value);
+        }
+    }
 
-            self.measure_screen();
-            self.figure_out_which_rows_to_display();
-            self.figure_out_which_columns_to_display();
-            self.figure_out_cell_dimensions();
-                        
+    fn show_frequencies_with_aggregate(&mut self) {
+        let numeric_columns: Vec<usize> = (0..self.csv.column_count())
+            .filter(|&column_index| self.csv.get_column(column_index).is_some_and(|column| column.column_type().is_numeric()))
+            .collect();
+        if numeric_columns.is_empty() {
+            self.message = Some("No numeric columns to sum up".to_owned());
+            return;
+        }
+        let lines = numeric_columns.iter().map(|&column_index| self.csv.column_name(column_index)).collect();
+        let title = format!("Sum and average for each {}", self.csv.column_name(self.cursor_column));
+        if let Some(selected) = Popup::new(title, lines).selectable(0).run() {
+            self.show_frequencies(Some(numeric_columns[selected]));
+        }
+    }
+
+    // Narrows the current filter (if any) down to rows where the column has exactly this value.
+    fn filter_by_value(&mut self, column_index: usize, value: &str) {
+        let header = self.csv.get_column(column_index).map_or("", |column| column.header());
+        let is_unique = (0..self.csv.column_count())
+            .filter(|&other| self.csv.get_column(other).is_some_and(|column| column.header() == header))
+            .count() == 1;
+        let column = if !header.is_empty() && is_unique {
+            format!("`{}`", header.replace('\\', "\\\\").replace('`', "\\`"))
+        } else {
+            format!("#{}", column_index + 1)
+        };
+        let condition = format!("{} == \"{}\"", column, value.replace('\\', "\\\\").replace('"', "\\\""));
+        let text = match &self.filter {
+            Some(filter) => format!("({}) && {}", filter.text(), condition),
+            None => condition,
+        };
+        match Filter::parse(&text, &self.csv) {
+            Ok(filter) => self.set_filter(filter),
+            Err(error) => self.message = Some(error.to_string()),
+        }
+    }
+
+    fn move_cursor_down_by(&mut self, rows: usize) {
+        let target = self.cursor_row + rows;
+        self.ensure_rows_loaded(target..target + 1);
+        self.cursor_row = std::cmp::max(self.cursor_row, std::cmp::min(target, self.row_count().saturating_sub(1)));
+    }
+
+    fn move_cursor_up_by(&mut self, rows: usize) {
+        self.cursor_row = self.cursor_row.saturating_sub(rows);
+    }
+
+    // Hidden columns are skipped over.
+    fn move_cursor_right_by(&mut self, columns: usize) {
+        for _ in 0..columns {
+            match (self.cursor_column + 1..self.csv.column_count()).find(|&column_index| !self.is_hidden(column_index)) {
+                Some(column_index) => self.cursor_column = column_index,
+                None => break,
+            }
+        }
+    }
+
+    fn move_cursor_left_by(&mut self, columns: usize) {
+        for _ in 0..columns {
+            match (0..self.cursor_column).rev().find(|&column_index| !self.is_hidden(column_index)) {
+                Some(column_index) => self.cursor_column = column_index,
+                None => break,
+            }
+        }
+    }
+
+    // Moves the viewport and the cursor together, so the cursor stays put on the screen.
+    fn scroll_down_by(&mut self, rows: usize) {
+        let target = self.first_row + rows;
+        self.ensure_rows_loaded(target..target + self.visible_rows);
+        let last_first_row = self.row_count().saturating_sub(self.visible_rows);
+        self.first_row = std::cmp::max(self.first_row, std::cmp::min(target, last_first_row));
+        self.move_cursor_down_by(rows);
+    }
+
+    fn scroll_up_by(&mut self, rows: usize) {
+        self.first_row = self.first_row.saturating_sub(rows);
+        self.move_cursor_up_by(rows);
+    }
+
+    fn move_cursor_to_last_row(&mut self) {
+        let row_count = self.count_all_rows();
+        self.cursor_row = row_count.saturating_sub(1);
+    }
+
+    // Also scrolls back past any frozen columns.
+    fn move_cursor_to_first_column(&mut self) {
+        self.cursor_column = (0..self.csv.column_count()).find(|&column_index| !self.is_hidden(column_index)).unwrap_or(0);
+        self.first_column = 0;
+    }
+
+    fn move_cursor_to_last_column(&mut self) {
+        let column_count = self.csv.column_count();
+        self.cursor_column = (0..column_count).rev().find(|&column_index| !self.is_hidden(column_index)).unwrap_or(0);
+    }
+
+    fn display_status_bar(&self) {
+        let column = self.csv.get_column(self.cursor_column);
+        let header = match column.map(|column| column.header()) {
+            Some(header) if !header.is_empty() => header.to_owned(),
+            _ => format!("column {}", self.cursor_column + 1),
+        };
+        let value = column
+            .and_then(|column| column.value(self.source_row(self.cursor_row)?))
+            .map_or_else(String::new, |item| item.lines().collect::<Vec<&str>>().join("↵"));
+
+        let row = match self.csv.total_row_count() {
+            Some(_) => format!("{}/{}", self.cursor_row + 1, self.row_count()),
+            None => format!("{}", self.cursor_row + 1),
+        };
+        let mut status = format!("row: {}, col: {}/{}, {}", 
+            row, self.cursor_column + 1, self.csv.column_count(), self.loading_status());
+        if self.history.is_modified() {
+            status.push_str(", modified (w to save)");
+        }
+        if self.csv.error_count() > 0 {
+            status.push_str(&format!(", malformed: {} (e to list)", self.csv.error_count()));
+        }
+        if !self.sort_keys.is_empty() {
+            let keys: Vec<String> = self.sort_keys.iter()
+                .map(|key| format!("{}{}", self.csv.get_column(key.column).map_or("", |column| column.header()), key.arrow()))
+                .collect();
+            status.push_str(&format!(", sorted: {}", keys.join(" ")));
+        }
+        if let Some(filter) = &self.filter {
+            status.push_str(&format!(", filtered: {}/{} rows ({})", self.row_count(), self.csv.row_count(), filter.text()));
+        }
+        if !self.hidden_columns.is_empty() {
+            status.push_str(&format!(", hidden: {} columns", self.hidden_columns.len()));
+        }
+        if self.search.is_some() {
+            status.push_str(&format!(", {}", self.search_counter));
+        }
+        match &self.message {
+            Some(message) => status.push_str(&format!(" │ {}", message)),
+            None => status.push_str(&format!(" │ {}: {}", header, value)),
+        }
 
-            for column_index in self.first_column..self.last_column {
+        ncurses::mv(self.screen_height as i32 - 1, 0);
+        ncurses::addstr(status.cut_or_pad_to(self.screen_width.saturating_sub(1), PADDING).join("").as_str());
+        ncurses::clrtoeol();
+    }
+
+    // Reads the rest of the file, with the progress in the status bar. Returns whether all of it was
+    // read, rather than Escape pressed.
+    fn load_all_rows(&mut self, purpose: &str) -> bool {
+        if self.csv.is_fully_loaded() {
+            return true;
+        }
+        let status_y = self.screen_height as i32 - 1;
+        let status_width = self.screen_width.saturating_sub(1);
+        let mut cancelled = false;
+        ncurses::nodelay(ncurses::stdscr(), true);
+        while self.csv.load_more(LOAD_STEP) {
+            let progress = match (self.csv.bytes_loaded(), self.csv.bytes_total()) {
+                (Some(loaded), Some(total)) if total > 0 => format!("{}%", loaded * 100 / total),
+                _ => format!("{} rows", self.csv.loaded_rows().end),
+            };
+            let status = format!("{}: {} (Esc to cancel)", purpose, progress);
+            ncurses::mv(status_y, 0);
+            ncurses::addstr(status.cut_or_pad_to(status_width, PADDING).join("").as_str());
+            ncurses::refresh();
+            if matches!(ncurses::get_wch(), Some(ncurses::WchResult::Char(0x1b))) {
+                cancelled = true;
+                break;
+            }
+        }
+        ncurses::nodelay(ncurses::stdscr(), false);
+        !cancelled
+    }
 
-                log::info!("column_index: {}", column_index);
+    fn loading_status(&self) -> String {
+        if let Some(total) = self.csv.total_row_count() {
+            return format!("rows: {}", total);
+        }
+        match (self.csv.bytes_loaded(), self.csv.bytes_total()) {
+            (Some(loaded), Some(total)) if total > 0 => 
+                format!("loaded: {} rows ({}%)", self.csv.row_count(), loaded * 100 / total),
+            _ => format!("loaded: {} rows…", self.csv.row_count()),
+        }
+    }
 
-                if let Some(column) = self.csv.get_column(column_index) {
-                    self.display_column_header(column_index, column);
-                    self.display_column_values(column_index, column);
+    fn update_search_counter(&mut self) {
+        if let Some(search) = &self.search {
+            let (count, position) = search.count(&self.csv, self.row_order.as_deref(), (self.cursor_row, self.cursor_column));
+            let more = if self.csv.is_fully_loaded() { "" } else { "+" };
+            self.search_counter = match position {
+                Some(position) => format!("match: {}/{}{}", position, count, more),
+                None => format!("matches: {}{}", count, more),
+            };
+        }
+    }
+
+    // Only looks among the loaded rows unless `load` is set, and then shows the progress of loading
+    // the rest, which Esc cancels.
+    fn move_cursor_to_match(&mut self, from: (usize, usize), direction: Direction, load: bool) {
+        let status_y = self.screen_height as i32 - 1;
+        let status_width = self.screen_width.saturating_sub(1);
+        let mut cancelled = false;
+        let mut progress = |csv: &CSVFile| {
+            if !load || cancelled {
+                return false;
+            }
+            let progress = match (csv.bytes_loaded(), csv.bytes_total()) {
+                (Some(loaded), Some(total)) if total > 0 => format!("{}%", loaded * 100 / total),
+                _ => format!("{} rows", csv.loaded_rows().end),
+            };
+            let status = format!("Searching: {} (Esc to cancel)", progress);
+            ncurses::mv(status_y, 0);
+            ncurses::addstr(status.cut_or_pad_to(status_width, PADDING).join("").as_str());
+            ncurses::refresh();
+            cancelled = matches!(ncurses::get_wch(), Some(ncurses::WchResult::Char(0x1b)));
+            !cancelled
+        };
+
+        ncurses::nodelay(ncurses::stdscr(), true);
+        let found = match &self.search {
+            Some(search) => search.find(&mut self.csv, self.row_order.as_deref(), from, direction, &mut progress),
+            None => None,
+        };
+        ncurses::nodelay(ncurses::stdscr(), false);
+        match found {
+            Some((row, column)) => {
+                self.cursor_row = row;
+                self.cursor_column = column;
+                self.hidden_columns.remove(&column);
+            }
+            None if self.search.is_some() => {
+                self.cursor_row = from.0;
+                self.cursor_column = from.1;
+                self.message = Some(match (cancelled, load) {
+                    (true, _) => "Search cancelled, pattern not found in the loaded rows",
+                    (false, false) if !self.csv.is_fully_loaded() => "Pattern not found in the loaded rows (Enter searches further)",
+                    _ => "Pattern not found",
+                }.to_owned());
+            }
+            None => return,
+        }
+        self.update_search_counter();
+    }
+
+    fn update_search(&mut self, pattern: &str, direction: Direction, origin: (usize, usize)) {
+        self.message = None;
+        match Search::new(pattern, self.search_options, direction) {
+            Ok(search) => {
+                self.search = Some(search);
+                self.move_cursor_to_match(origin, direction, false);
+            }
+            Err(error) => {
+                self.search = None;
+                self.cursor_row = origin.0;
+                self.cursor_column = origin.1;
+                self.message = Some(format!("Invalid pattern: {}", error).replace('\n', " "));
+            }
+        }
+    }
+
+    // Moves the cursor to the first match as the pattern is typed. Escape restores the cursor and
+    // the previous search.
+    fn read_search(&mut self, direction: Direction) {
+        let origin = (self.cursor_row, self.cursor_column);
+        let origin_view = (self.first_row, self.first_column);
+        let previous_search = self.search.take();
+        let prefix = match direction { Direction::Forward => "/", Direction::Backward => "?" };
+        let mut editor = LineEditor::default();
+
+        ncurses::curs_set(ncurses::CURSOR_VISIBILITY::CURSOR_VISIBLE);
+        loop {
+            self.display();
+            let hints = match &self.message {
+                Some(message) => format!("  {}", message),
+                None => format!("  {}", self.search_options.describe()),
+            };
+            editor.draw(self.screen_height as i32 - 1, prefix, hints.as_str(), self.screen_width);
+
+            let input = match ncurses::get_wch() {
+                Some(input) => input,
+                None => continue,
+            };
+            match editor.handle(input) {
+                Edit::Submit => {
+                    if editor.text().is_empty() {
+                        self.search = previous_search;
+                    } else if self.search.is_some() {
+                        // Typing only looks among the loaded rows, the nearest match may be past them.
+                        self.message = None;
+                        self.move_cursor_to_match(origin, direction, true);
+                    }
+                    break;
+                }
+                Edit::Cancel => {
+                    self.search = previous_search;
+                    self.cursor_row = origin.0;
+                    self.cursor_column = origin.1;
+                    self.first_row = origin_view.0;
+                    self.first_column = origin_view.1;
+                    self.message = None;
+                    break;
+                }
+                Edit::Changed => self.update_search(&editor.text(), direction, origin),
+                Edit::Control('\u{14}') => { // CTRL-T
+                    self.search_options.ignore_case = !self.search_options.ignore_case;
+                    self.update_search(&editor.text(), direction, origin);
+                }
+                Edit::Control('\u{17}') => { // CTRL-W
+                    self.search_options.whole_cell = !self.search_options.whole_cell;
+                    self.update_search(&editor.text(), direction, origin);
+                }
+                Edit::Control('\u{18}') => { // CTRL-X
+                    self.search_options.regex = !self.search_options.regex;
+                    self.update_search(&editor.text(), direction, origin);
+                }
+                _ => (),
+            }
+        }
+        ncurses::curs_set(ncurses::CURSOR_VISIBILITY::CURSOR_INVISIBLE);
+        self.update_search_counter();
+    }
+
+    fn repeat_search(&mut self, reverse: bool) {
+        let direction = match &self.search {
+            Some(search) if reverse => search.direction().reversed(),
+            Some(search) => search.direction(),
+            None => return,
+        };
+        self.move_cursor_to_match((self.cursor_row, self.cursor_column), direction, true);
+    }
+
+    // Rebuilds the display from the rows that pass `filter`, ordered according to `sort_keys`.
+    // Keeps the cursor on the same row, or on the next one shown if that row was filtered out.
+    // Returns false, with the display left as it was, if loading the rows for it is cancelled.
+    fn update_row_order(&mut self) -> bool {
+        let cursor_row_index = self.source_row(self.cursor_row).unwrap_or(0);
+
+        if self.sort_keys.is_empty() && self.filter.is_none() {
+            self.row_order = None;
+        } else {
+            let purpose = if self.filter.is_some() { "Loading the whole file to filter it" } else { "Loading the whole file to sort it" };
+            if !self.load_all_rows(purpose) {
+                return false;
+            }
+            let rows: Vec<usize> = match &self.filter {
+                Some(filter) => (0..self.csv.row_count()).filter(|&row_index| filter.matches(&self.csv, row_index)).collect(),
+                None => (0..self.csv.row_count()).collect(),
+            };
+            self.row_order = Some(sort::sort_rows(&self.csv, rows, &self.sort_keys));
+        }
+
+        self.cursor_row = match (self.display_row_of(cursor_row_index), &self.row_order) {
+            (Some(row), _) => row,
+            (None, Some(order)) => order.iter().position(|&row_index| row_index > cursor_row_index).unwrap_or(0),
+            (None, None) => 0,
+        };
+        self.update_search_counter();
+        true
+    }
+
+    pub fn set_filter(&mut self, filter: Filter) {
+        let previous = self.filter.replace(filter);
+        if !self.update_row_order() {
+            self.filter = previous;
+            self.message = Some("Not filtered: the file is only partly loaded".to_owned());
+        }
+    }
+
+    fn clear_filter(&mut self) {
+        let previous = self.filter.take();
+        if !self.update_row_order() {
+            self.filter = previous;
+            self.message = Some("Filter kept: the file is only partly loaded".to_owned());
+        }
+    }
+
+    // The expression is checked as it is typed, but only applied on Enter. An empty expression
+    // removes the filter.
+    fn read_filter(&mut self) {
+        let mut editor = LineEditor::new(self.filter.as_ref().map_or("", |filter| filter.text()));
+        let mut hint = String::new();
+
+        ncurses::curs_set(ncurses::CURSOR_VISIBILITY::CURSOR_VISIBLE);
+        loop {
+            self.display();
+            editor.draw(self.screen_height as i32 - 1, "filter: ", hint.as_str(), self.screen_width);
+
+            let input = match ncurses::get_wch() {
+                Some(input) => input,
+                None => continue,
+            };
+            match editor.handle(input) {
+                Edit::Submit => {
+                    let text = editor.text();
+                    if text.trim().is_empty() {
+                        self.clear_filter();
+                        break;
+                    }
+                    match Filter::parse(&text, &self.csv) {
+                        Ok(filter) => {
+                            self.set_filter(filter);
+                            break;
+                        }
+                        Err(error) => hint = format!("  {}", error),
+                    }
                 }
+                Edit::Cancel => break,
+                Edit::Changed => {
+                    let text = editor.text();
+                    hint = match Filter::parse(&text, &self.csv) {
+                        Err(error) if !text.trim().is_empty() => format!("  {}", error),
+                        _ => String::new(),
+                    };
+                }
+                _ => (),
+            }
+        }
+        ncurses::curs_set(ncurses::CURSOR_VISIBILITY::CURSOR_INVISIBLE);
+    }
+
+    // Reads a line of text in the status bar. Returns nothing if Escape was pressed.
+    fn read_line(&mut self, prompt: &str, text: &str) -> Option<String> {
+        let mut editor = LineEditor::new(text);
+
+        ncurses::curs_set(ncurses::CURSOR_VISIBILITY::CURSOR_VISIBLE);
+        let text = loop {
+            self.display();
+            editor.draw(self.screen_height as i32 - 1, prompt, "", self.screen_width);
+
+            let input = match ncurses::get_wch() {
+                Some(input) => input,
+                None => continue,
+            };
+            match editor.handle(input) {
+                Edit::Submit => break Some(editor.text()),
+                Edit::Cancel => break None,
+                _ => (),
+            }
+        };
+        ncurses::curs_set(ncurses::CURSOR_VISIBILITY::CURSOR_INVISIBLE);
+        text
+    }
+
+    // Edits need the whole file in memory, so it is loaded first, which can be cancelled.
+    fn can_edit(&mut self) -> bool {
+        if self.derived {
+            self.message = Some("This table is computed from the file and cannot be edited".to_owned());
+            return false;
+        }
+        if !self.load_all_rows("Loading the whole file to edit it") {
+            self.message = Some("Not edited: the file is only partly loaded".to_owned());
+            return false;
+        }
+        true
+    }
+
+    // Values that span lines are edited on one line, with the line breaks shown as ↵.
+    fn edit_cell(&mut self) {
+        if !self.can_edit() || self.cursor_column >= self.csv.column_count() {
+            return;
+        }
+        let row_index = match self.source_row(self.cursor_row) {
+            Some(row_index) => row_index,
+            None => return,
+        };
+        let text = self.csv.get_column(self.cursor_column)
+            .and_then(|column| column.value(row_index))
+            .map_or_else(String::new, |item| item.lines().collect::<Vec<&str>>().join("↵"));
+        let prompt = format!("{}: ", self.csv.column_name(self.cursor_column));
+        if let Some(text) = self.read_line(&prompt, &text) {
+            let after = text.replace('↵', "\n");
+            let before = self.csv.set_value(row_index, self.cursor_column, &after);
+            if before.as_deref() != Some(after.as_str()) {
+                self.history.record(Change::Value { row: row_index, column: self.cursor_column, before, after });
+            }
+            self.update_search_counter();
+        }
+    }
+
+    fn edit_header(&mut self) {
+        if !self.can_edit() || self.cursor_column >= self.csv.column_count() {
+            return;
+        }
+        let header = self.csv.get_column(self.cursor_column).map_or_else(String::new, |column| column.header().to_owned());
+        if let Some(header) = self.read_line("header: ", &header) {
+            let before = self.csv.set_header(self.cursor_column, header.clone());
+            if before != header {
+                self.history.record(Change::Header { column: self.cursor_column, before, after: header });
+            }
+        }
+    }
+
+    // Adds an empty row below the cursor, which moves to it. In a sorted or filtered view the row is
+    // shown below the cursor too, until the rows are sorted or filtered again.
+    fn insert_row(&mut self) {
+        if !self.can_edit() {
+            return;
+        }
+        let (row, row_index) = match self.source_row(self.cursor_row) {
+            Some(row_index) => (self.cursor_row + 1, row_index + 1),
+            None => (0, self.csv.row_count()),
+        };
+        let fields = vec![String::new(); std::cmp::max(self.csv.column_count(), 1)];
+        self.csv.insert_row(row_index, fields.clone());
+        self.history.record(Change::InsertRow { row: row_index, fields });
+        if let Some(order) = self.row_order.as_mut() {
+            for source_row in order.iter_mut().filter(|source_row| **source_row >= row_index) {
+                *source_row += 1;
+            }
+            order.insert(row, row_index);
+        }
+        self.cursor_row = row;
+        self.update_search_counter();
+    }
+
+    fn delete_row(&mut self) {
+        if !self.can_edit() {
+            return;
+        }
+        let row_index = match self.source_row(self.cursor_row) {
+            Some(row_index) => row_index,
+            None => return,
+        };
+        let fields = self.csv.delete_row(row_index);
+        self.history.record(Change::DeleteRow { row: row_index, fields });
+        self.message = Some(format!("Deleted row {} (u to undo)", row_index + 1));
+        if let Some(order) = self.row_order.as_mut() {
+            order.retain(|&source_row| source_row != row_index);
+            for source_row in order.iter_mut().filter(|source_row| **source_row > row_index) {
+                *source_row -= 1;
+            }
+        }
+        self.cursor_row = std::cmp::min(self.cursor_row, self.row_count().saturating_sub(1));
+        self.update_search_counter();
+    }
+
+    // Sort keys, column widths and the filter refer to columns by index, so they have to follow the
+    // columns around. The filter is parsed again, in case it names columns by header.
+    fn shift_columns(&mut self, from_column: usize, inserted: bool) {
+        let shift = |column_index: usize| match column_index {
+            column_index if column_index < from_column => Some(column_index),
+            column_index if inserted => Some(column_index + 1),
+            column_index if column_index == from_column => None,
+            column_index => Some(column_index - 1),
+        };
+        self.sort_keys = self.sort_keys.drain(..)
+            .filter_map(|key| shift(key.column).map(|column| SortKey { column, order: key.order }))
+            .collect();
+        self.column_widths = self.column_widths.drain()
+            .filter_map(|(column_index, width)| shift(column_index).map(|column_index| (column_index, width)))
+            .collect();
+        self.hidden_columns = self.hidden_columns.iter().filter_map(|&column_index| shift(column_index)).collect();
+        if self.hidden_columns.len() >= self.csv.column_count() {
+            self.hidden_columns.clear();
+        }
+        if let Some(filter) = self.filter.take() {
+            match Filter::parse(filter.text(), &self.csv) {
+                Ok(filter) => self.filter = Some(filter),
+                Err(error) => self.message = Some(format!("Filter removed: {}", error)),
+            }
+        }
+        self.cursor_column = std::cmp::min(self.cursor_column, self.csv.column_count().saturating_sub(1));
+        self.update_row_order();
+    }
+
+    // Adds a column to the right of the cursor, which moves to it.
+    fn insert_column(&mut self) {
+        if !self.can_edit() {
+            return;
+        }
+        let column_index = if self.csv.column_count() == 0 { 0 } else { self.cursor_column + 1 };
+        let header = if self.headers {
+            match self.read_line("new column: ", "") {
+                Some(header) => header,
+                None => return,
             }
+        } else {
+            String::new()
+        };
+        self.csv.insert_column(column_index, header.clone());
+        self.history.record(Change::InsertColumn { column: column_index, header });
+        self.shift_columns(column_index, true);
+        self.cursor_column = column_index;
+    }
 
-            ncurses::mv(self.screen_height as i32 - 1, 0);
-            ncurses::addstr(&format!("row: {}-{}, cols: {}-{}", self.first_row, self.last_row, self.first_column, self.last_column));
+    fn delete_column(&mut self) {
+        if !self.can_edit() || self.cursor_column >= self.csv.column_count() {
+            return;
+        }
+        let column_index = self.cursor_column;
+        if let Some(values) = self.csv.delete_column(column_index) {
+            self.history.record(Change::DeleteColumn { column: column_index, values });
+            self.message = Some(format!("Deleted column {} (u to undo)", column_index + 1));
+        }
+        self.shift_columns(column_index, false);
+    }
 
-            let input = ncurses::get_wch().unwrap();
+    fn undo(&mut self) {
+        if matches!(self.history.next_undo(), Some(Change::Sort { .. })) && !self.load_all_rows("Loading the whole file to sort it") {
+            self.message = Some("Not undone: the file is only partly loaded".to_owned());
+            return;
+        }
+        match self.history.take_undo() {
+            Some(mut step) => {
+                step.change = step.change.revert(&mut self.csv);
+                self.follow_change(&step.change, true);
+                self.history.undone(step);
+            }
+            None => self.message = Some("Nothing to undo".to_owned()),
+        }
+    }
+
+    fn redo(&mut self) {
+        if matches!(self.history.next_redo(), Some(Change::Sort { .. })) && !self.load_all_rows("Loading the whole file to sort it") {
+            self.message = Some("Not redone: the file is only partly loaded".to_owned());
+            return;
+        }
+        match self.history.take_redo() {
+            Some(mut step) => {
+                step.change = step.change.apply(&mut self.csv);
+                self.follow_change(&step.change, false);
+                self.history.redone(step);
+            }
+            None => self.message = Some("Nothing to redo".to_owned()),
+        }
+    }
+
+    // Brings the view in line with a change that was just undone or redone, and moves the cursor
+    // to where it happened.
+    fn follow_change(&mut self, change: &Change, undone: bool) {
+        match change {
+            Change::Value { row, column, .. } => {
+                self.cursor_column = *column;
+                if let Some(row) = self.display_row_of(*row) {
+                    self.cursor_row = row;
+                }
+            }
+            Change::Header { column, .. } => self.cursor_column = *column,
+            Change::InsertRow { row, .. } | Change::DeleteRow { row, .. } => {
+                self.update_row_order();
+                if let Some(row) = self.display_row_of(*row) {
+                    self.cursor_row = row;
+                }
+            }
+            Change::InsertColumn { column, .. } | Change::DeleteColumn { column, .. } => {
+                let inserted = matches!(change, Change::InsertColumn { .. }) != undone;
+                self.shift_columns(*column, inserted);
+                self.cursor_column = std::cmp::min(*column, self.csv.column_count().saturating_sub(1));
+            }
+            Change::Sort { before, after } => {
+                self.sort_keys = if undone { before.clone() } else { after.clone() };
+                self.update_row_order();
+            }
+        }
+        self.cursor_row = std::cmp::min(self.cursor_row, self.row_count().saturating_sub(1));
+        let done = if undone { "Undone" } else { "Redone" };
+        self.message = Some(format!("{}: {}", done, change.describe()));
+        self.update_search_counter();
+    }
+
+    fn save_to(&mut self, path: &Path) -> bool {
+        if !self.confirm_altered() {
+            return false;
+        }
+        match self.csv.save(path, &self.dialect.build_writer(), self.headers) {
+            Ok(()) => {
+                self.history.saved();
+                self.message = Some(format!("Saved {} rows to {}", self.csv.row_count(), path.display()));
+                true
+            }
+            Err(error) => {
+                self.message = Some(error.to_string());
+                false
+            }
+        }
+    }
+
+    // Files read from stdin have nowhere to be saved to, so they are saved as a new file.
+    fn save(&mut self) -> bool {
+        if !self.can_edit() {
+            return false;
+        }
+        match self.save_path.clone() {
+            Some(path) => self.save_to(&path),
+            None => self.save_as(),
+        }
+    }
+
+    fn save_as(&mut self) -> bool {
+        if !self.can_edit() {
+            return false;
+        }
+        let current = self.save_path.as_ref().map_or_else(String::new, |path| path.display().to_string());
+        let path = match self.read_line("save as: ", &current) {
+            Some(path) if !path.trim().is_empty() => PathBuf::from(path.trim()),
+            _ => return false,
+        };
+        if self.save_path.as_ref() != Some(&path) && !self.confirm_overwrite(&path) {
+            return false;
+        }
+        if !self.save_to(&path) {
+            return false;
+        }
+        self.save_path = Some(path);
+        true
+    }
+
+    // Values are saved as they are shown, which is not how they are in the file if reading them
+    // trimmed whitespace or replaced invalid UTF-8. That would change rows that were never edited.
+    fn confirm_altered(&mut self) -> bool {
+        if !self.csv.is_altered() || self.save_altered {
+            return true;
+        }
+        let lines = vec![
+            "Some values were changed when the file was read:".to_owned(),
+            "whitespace around them was trimmed (see --trim-whitespace),".to_owned(),
+            "or invalid UTF-8 in them was replaced.".to_owned(),
+            String::new(),
+            "Saving writes them as shown, also in rows that were not edited.".to_owned(),
+            String::new(),
+            "y: save anyway".to_owned(),
+            "Esc: go back".to_owned(),
+        ];
+        let mut popup = Popup::new("Save changed values?".to_owned(), lines).command_keys("y");
+        popup.run();
+        self.save_altered = popup.pressed_key() == Some('y');
+        self.save_altered
+    }
+
+    fn confirm_overwrite(&mut self, path: &Path) -> bool {
+        if !path.exists() {
+            return true;
+        }
+        let lines = vec![format!("{} already exists.", path.display()), String::new(), "y: overwrite it".to_owned(), "Esc: go back".to_owned()];
+        let mut popup = Popup::new("Overwrite?".to_owned(), lines).command_keys("y");
+        popup.run();
+        popup.pressed_key() == Some('y')
+    }
+
+    // Writes the rows and columns shown, in the order shown, to a file. CSV can be written with
+    // another delimiter than the file has.
+    fn export(&mut self) {
+        let lines = Format::ALL.iter().map(|format| format.description().to_owned()).collect();
+        let format = match Popup::new("Export as".to_owned(), lines).selectable(0).run() {
+            Some(selected) => Format::ALL[selected],
+            None => return,
+        };
+
+        let mut dialect = self.dialect.clone();
+        if format == Format::Csv {
+            let delimiter = match self.read_line("delimiter (\\t for tab): ", &(dialect.delimiter as char).to_string()) {
+                Some(delimiter) => delimiter,
+                None => return,
+            };
+            dialect.delimiter = match delimiter.as_bytes() {
+                b"\\t" => b'\t',
+                [delimiter] => *delimiter,
+                _ => {
+                    self.message = Some("The delimiter has to be a single ASCII character".to_owned());
+                    return;
+                }
+            };
+        }
+
+        let suggested = match &self.save_path {
+            Some(path) => path.with_extension(format!("view.{}", format.extension())),
+            None => PathBuf::from(format!("export.{}", format.extension())),
+        };
+        let path = match self.read_line("export to: ", &suggested.display().to_string()) {
+            Some(path) if !path.trim().is_empty() => PathBuf::from(path.trim()),
+            _ => return,
+        };
+        if !self.confirm_overwrite(&path) {
+            return;
+        }
+
+        if !self.load_all_rows("Loading the whole file to export it") {
+            self.message = Some("Export cancelled".to_owned());
+            return;
+        }
+        let rows: Vec<usize> = match &self.row_order {
+            Some(order) => order.clone(),
+            None => (0..self.csv.row_count()).collect(),
+        };
+        let columns = (0..self.csv.column_count()).filter(|&column_index| !self.is_hidden(column_index)).collect();
+        let table = Table { csv: &self.csv, rows, columns, headers: self.headers };
+        let written = std::fs::File::create(&path).and_then(|file| {
+            let mut output = std::io::BufWriter::new(file);
+            table.write(format, &dialect, &mut output)?;
+            output.flush()
+        });
+        self.message = Some(match written {
+            Ok(()) => format!("Exported {} rows to {}", table.rows.len(), path.display()),
+            Err(error) => format!("Cannot export to {}: {}", path.display(), error),
+        });
+    }
+
+    // Asks what to do with unsaved changes, if there are any.
+    fn can_quit(&mut self) -> bool {
+        if !self.history.is_modified() {
+            return true;
+        }
+        let lines = vec!["y: save and quit".to_owned(), "n: quit without saving".to_owned(), "Esc: go back".to_owned()];
+        let mut popup = Popup::new("Unsaved changes".to_owned(), lines).command_keys("yn");
+        popup.run();
+        match popup.pressed_key() {
+            Some('y') => self.save(),
+            Some('n') => true,
+            _ => false,
+        }
+    }
+
+    fn set_sort_keys(&mut self, sort_keys: Vec<SortKey>) {
+        let before = std::mem::replace(&mut self.sort_keys, sort_keys.clone());
+        if !self.update_row_order() {
+            self.sort_keys = before;
+            self.message = Some("Not sorted: the file is only partly loaded".to_owned());
+            return;
+        }
+        if sort_keys != before {
+            self.history.record(Change::Sort { before, after: sort_keys });
+        }
+    }
+
+    fn sort_by(&mut self, order: SortOrder) {
+        self.set_sort_keys(vec![SortKey { column: self.cursor_column, order }]);
+    }
+
+    // Adds the current column as a less significant key, or changes its order if it is already a key.
+    fn sort_also_by(&mut self, order: SortOrder) {
+        let column = self.cursor_column;
+        let mut sort_keys = self.sort_keys.clone();
+        match sort_keys.iter_mut().find(|key| key.column == column) {
+            Some(key) => key.order = order,
+            None => sort_keys.push(SortKey { column, order }),
+        }
+        self.set_sort_keys(sort_keys);
+    }
+
+    // Shows the record under the cursor as one line per field (or more, for values that span lines)
+    // with the headers on the left. The cursor column is the selected field, kept on screen.
+    fn display_record(&mut self) {
+        let column_count = self.csv.column_count();
+        let row_index = self.source_row(self.cursor_row);
+        let available_lines = self.screen_height.saturating_sub(2);
+
+        let csv = &self.csv;
+        let hidden = &self.hidden_columns;
+        let field_height = |column_index: usize| match row_index {
+            _ if hidden.contains(&column_index) => 0,
+            Some(row_index) => csv.get_column(column_index)
+                .and_then(|column| column.value(row_index))
+                .map_or(1, |item| std::cmp::max(item.height(), 1)),
+            None => 1,
+        };
+        if self.cursor_column < self.record_first_field {
+            self.record_first_field = self.cursor_column;
+        }
+        while self.record_first_field < self.cursor_column
+            && (self.record_first_field..=self.cursor_column).map(field_height).sum::<usize>() > available_lines {
+            self.record_first_field += 1;
+        }
+
+        let label_width = (0..column_count)
+            .map(|column_index| display_width(&self.csv.column_name(column_index)))
+            .max().unwrap_or(0)
+            .clamp(1, std::cmp::max(self.screen_width / 3, 1));
+        let value_width = std::cmp::max(self.screen_width.saturating_sub(label_width + 3), 1);
+
+        let title = match row_index {
+            Some(row_index) => format!("record {} ({}/{})", row_index + 1, self.cursor_row + 1, self.row_count()),
+            None => "no records".to_owned(),
+        };
+        ncurses::attron(ncurses::A_BOLD());
+        ncurses::attron(ncurses::COLOR_PAIR(COLOR_HEADER_PAIR_EVEN));
+        ncurses::mv(0, 0);
+        ncurses::addstr(title.cut_or_pad_to(self.screen_width, PADDING).join("").as_str());
+        ncurses::attroff(ncurses::COLOR_PAIR(COLOR_HEADER_PAIR_EVEN));
+        ncurses::attroff(ncurses::A_BOLD());
+
+        let has_error = row_index.is_some_and(|row_index| self.csv.row_error(row_index).is_some());
+        let mut y = 1;
+        for column_index in self.record_first_field..column_count {
+            if self.is_hidden(column_index) {
+                continue;
+            }
+            let column = match self.csv.get_column(column_index) {
+                Some(column) => column,
+                None => break,
+            };
+            let item = row_index.and_then(|row_index| column.value(row_index));
+            let mut lines: Vec<String> = item.map_or_else(Vec::new, |item| item.lines().map(|line| line.to_owned()).collect());
+            if lines.is_empty() {
+                lines.push(String::new());
+            }
+
+            let is_cursor = column_index == self.cursor_column;
+            let is_even = column_index % 2 == 0;
+            let is_match = self.search.as_ref().is_some_and(|search| item.is_some_and(|item| search.matches(item)));
+            let label_colors = if is_cursor { COLOR_CURSOR_PAIR } else if is_even { COLOR_HEADER_PAIR_EVEN } else { COLOR_HEADER_PAIR_ODD };
+            let value_colors = if is_cursor {
+                COLOR_CURSOR_PAIR
+            } else if is_match {
+                COLOR_MATCH_PAIR
+            } else if has_error {
+                COLOR_ERROR_PAIR
+            } else if is_even {
+                COLOR_VALUES_PAIR_EVEN
+            } else {
+                COLOR_VALUES_PAIR_ODD
+            };
+
+            for (line_number, line) in lines.iter().enumerate() {
+                if y > available_lines {
+                    return;
+                }
+                let label = if line_number == 0 { self.csv.column_name(column_index) } else { String::new() };
+                ncurses::mv(y as i32, 0);
+                ncurses::attron(ncurses::COLOR_PAIR(label_colors));
+                ncurses::addstr(label.cut_or_pad_to(label_width, PADDING).join("").as_str());
+                ncurses::attroff(ncurses::COLOR_PAIR(label_colors));
+                ncurses::addstr(" │ ");
+                ncurses::attron(ncurses::COLOR_PAIR(value_colors));
+                ncurses::addstr(line.cut_or_pad_to(value_width, PADDING).join("").as_str());
+                ncurses::attroff(ncurses::COLOR_PAIR(value_colors));
+                y += 1;
+            }
+        }
+    }
+
+    fn screen_too_small(&self) -> bool {
+        self.screen_height < MIN_SCREEN_HEIGHT || self.screen_width < MIN_SCREEN_WIDTH
+    }
+
+    fn display(&mut self) {
+        if self.measure_screen() {
+            // Nothing drawn at the old size can be trusted, so have curses repaint everything.
+            ncurses::clear();
+        }
+
+        if self.screen_too_small() {
+            ncurses::erase();
+            let message = format!("Terminal too small, need {}x{}", MIN_SCREEN_WIDTH, MIN_SCREEN_HEIGHT);
+            ncurses::mv(0, 0);
+            ncurses::addstr(message.cut_or_pad_to(self.screen_width.saturating_sub(1), PADDING).join("").as_str());
+            return;
+        }
+        self.row_height = std::cmp::min(self.row_height, self.screen_height - 2);
+
+        self.figure_out_which_rows_to_display();
+
+        if self.record_view {
+            ncurses::erase();
+            self.display_record();
+            self.display_status_bar();
+            return;
+        }
+
+        self.figure_out_which_columns_to_display();
+
+        // Column widths change as rows load, so nothing from the previous frame can be assumed to be covered.
+        ncurses::erase();
+
+        for &(column_index, _, _) in self.column_layout.iter() {
+
+            log::info!("column_index: {}", column_index);
+
+            if let Some(column) = self.csv.get_column(column_index) {
+                self.display_column_header(column_index, column);
+                self.display_column_values(column_index, column);
+            }
+        }
+        self.display_frozen_separator();
+        self.display_gutter();
+
+        self.display_status_bar();
+    }
+
+    pub fn run(&mut self) {
+        self.run_view();
+    }
+
+    // Returns the row picked with Enter, if the display is derived.
+    fn run_view(&mut self) -> Option<usize> {
+
+        loop {
+
+            self.display();
+            self.message = None;
+
+            // Reading can be interrupted, by a resize for one.
+            let input = match ncurses::get_wch() {
+                Some(input) => input,
+                None => continue,
+            };
 
             match input {
                 ncurses::WchResult::KeyCode(value) => {
                     let bytes = value.to_be_bytes();                                        
                     log::info!("key input: {:?}", bytes);
                     match bytes {
-                        [0, 0, 1, 2] => if self.last_row < self.csv.row_count() { self.first_row += 1 }, // DOWN
-                        [0, 0, 1, 3] => if self.first_row > 0 { self.first_row -= 1 }, // UP
-                        [0, 0, 1, 4] => if self.first_column > 0 { self.first_column -= 1 }, // LEFT
-                        [0, 0, 1, 5] => if self.last_column < self.csv.column_count() { self.first_column += 1 }, // RIGHT
+                        [0, 0, 1, 154] => (), // RESIZE, the screen is measured again when displayed
+                        _ if self.screen_too_small() => (),
+                        [0, 0, 1, 2] if self.record_view => self.move_cursor_right_by(1), // DOWN, next field
+                        [0, 0, 1, 3] if self.record_view => self.move_cursor_left_by(1), // UP, previous field
+                        [0, 0, 1, 4] if self.record_view => self.move_cursor_up_by(1), // LEFT, previous record
+                        [0, 0, 1, 5] if self.record_view => self.move_cursor_down_by(1), // RIGHT, next record
+                        [0, 0, 1, 82] if self.record_view => self.move_cursor_right_by(std::cmp::max(self.screen_height.saturating_sub(2), 1)), // PAGE DOWN
+                        [0, 0, 1, 83] if self.record_view => self.move_cursor_left_by(std::cmp::max(self.screen_height.saturating_sub(2), 1)), // PAGE UP
+                        [0, 0, 1, 6] if self.record_view => self.move_cursor_to_first_column(), // HOME
+                        [0, 0, 1, 104] if self.record_view => self.move_cursor_to_last_column(), // END
+                        [0, 0, 1, 2] => self.move_cursor_down_by(1), // DOWN
+                        [0, 0, 1, 3] => self.move_cursor_up_by(1), // UP
+                        [0, 0, 1, 4] => self.move_cursor_left_by(1), // LEFT
+                        [0, 0, 1, 5] => self.move_cursor_right_by(1), // RIGHT
+                        [0, 0, 1, 82] => self.scroll_down_by(self.visible_rows), // PAGE DOWN
+                        [0, 0, 1, 83] => self.scroll_up_by(self.visible_rows), // PAGE UP
+                        [0, 0, 1, 6] => self.cursor_row = 0, // HOME
+                        [0, 0, 1, 104] => self.move_cursor_to_last_row(), // END
+                        [0, 0, 1, 87] if self.derived => break self.source_row(self.cursor_row), // ENTER
+                        [0, 0, 1, 87] => self.show_cell(), // ENTER
                         _ => (),                        
                     }
                 }
@@ -238,7 +1822,54 @@ impl CSVDisplay {
                     let characters = [bytes[0] as char, bytes[1] as char, bytes[2] as char, bytes[3] as char];
                     log::info!("char input: {:?}", characters);
                     match characters {
-                        [ '\0', '\0', '\0', 'q' ] => break,
+                        [ '\0', '\0', '\0', 'q' ] if self.can_quit() => break None,
+                        _ if self.screen_too_small() => (),
+                        [ '\0', '\0', '\0', '\n' ] | [ '\0', '\0', '\0', '\r' ] if self.derived => break self.source_row(self.cursor_row),
+                        [ '\0', '\0', '\0', '\n' ] | [ '\0', '\0', '\0', '\r' ] => self.show_cell(),
+                        [ '\0', '\0', '\0', 'e' ] => self.show_errors(),
+                        [ '\0', '\0', '\0', 'i' ] => self.show_statistics(),
+                        [ '\0', '\0', '\0', 'c' ] => self.show_frequencies(None),
+                        [ '\0', '\0', '\0', 'C' ] => self.show_frequencies_with_aggregate(),
+                        [ '\0', '\0', '\0', 'g' ] => self.cursor_row = 0,
+                        [ '\0', '\0', '\0', 'G' ] => self.move_cursor_to_last_row(),
+                        [ '\0', '\0', '\0', '\u{4}' ] => self.scroll_down_by(std::cmp::max(self.visible_rows / 2, 1)), // CTRL-D
+                        [ '\0', '\0', '\0', '\u{15}' ] => self.scroll_up_by(std::cmp::max(self.visible_rows / 2, 1)), // CTRL-U
+                        [ '\0', '\0', '\0', '0' ] => self.move_cursor_to_first_column(),
+                        [ '\0', '\0', '\0', '$' ] => self.move_cursor_to_last_column(),
+                        [ '\0', '\0', '\0', '>' ] => self.widen_column(self.cursor_column),
+                        [ '\0', '\0', '\0', '<' ] => self.narrow_column(self.cursor_column),
+                        [ '\0', '\0', '\0', 'f' ] => { self.column_widths.insert(self.cursor_column, ColumnWidth::Fit); }
+                        [ '\0', '\0', '\0', 'F' ] => self.fit_all_columns(),
+                        [ '\0', '\0', '\0', 'z' ] => self.toggle_frozen_columns(),
+                        [ '\0', '\0', '\0', '#' ] => self.cycle_row_numbers(),
+                        [ '\0', '\0', '\0', 'r' ] => self.record_view = !self.record_view,
+                        [ '\0', '\0', '\0', '+' ] => self.increase_row_height(),
+                        [ '\0', '\0', '\0', '-' ] => self.decrease_row_height(),
+                        [ '\0', '\0', '\0', '/' ] => self.read_search(Direction::Forward),
+                        [ '\0', '\0', '\0', '?' ] => self.read_search(Direction::Backward),
+                        [ '\0', '\0', '\0', 'n' ] => self.repeat_search(false),
+                        [ '\0', '\0', '\0', 'N' ] => self.repeat_search(true),
+                        [ '\0', '\0', '\0', '\u{1b}' ] => self.search = None, // ESCAPE
+                        [ '\0', '\0', '\0', 's' ] => self.sort_by(SortOrder::Ascending),
+                        [ '\0', '\0', '\0', 'S' ] => self.sort_by(SortOrder::Descending),
+                        [ '\0', '\0', '\0', 'a' ] => self.sort_also_by(SortOrder::Ascending),
+                        [ '\0', '\0', '\0', 'A' ] => self.sort_also_by(SortOrder::Descending),
+                        [ '\0', '\0', '\0', 'o' ] => self.set_sort_keys(Vec::new()),
+                        [ '\0', '\0', '\0', '|' ] => self.read_filter(),
+                        [ '\0', '\0', '\0', '\\' ] => self.clear_filter(),
+                        [ '\0', '\0', '\0', 'E' ] => self.edit_cell(),
+                        [ '\0', '\0', '\0', 'H' ] => self.edit_header(),
+                        [ '\0', '\0', '\0', 'O' ] => self.insert_row(),
+                        [ '\0', '\0', '\0', 'D' ] => self.delete_row(),
+                        [ '\0', '\0', '\0', 'I' ] => self.insert_column(),
+                        [ '\0', '\0', '\0', 'X' ] => self.delete_column(),
+                        [ '\0', '\0', '\0', 'h' ] => self.hide_column(),
+                        [ '\0', '\0', '\0', 'v' ] => self.show_all_columns(),
+                        [ '\0', '\0', '\0', 'x' ] => self.export(),
+                        [ '\0', '\0', '\0', 'u' ] => self.undo(),
+                        [ '\0', '\0', '\0', '\u{12}' ] => self.redo(), // CTRL-R
+                        [ '\0', '\0', '\0', 'w' ] => { self.save(); }
+                        [ '\0', '\0', '\0', 'W' ] => { self.save_as(); }
                         _ => (),
                     }
                 }
@@ -249,6 +1880,9 @@ impl CSVDisplay {
 
 impl Drop for CSVDisplay {
     fn drop(&mut self) {
+        if self.derived {
+            return;
+        }
         ncurses::endwin();
     }
 }
\ No newline at end of file
@@ -0,0 +1,251 @@
+use regex::{Regex, RegexBuilder};
+
+use crate::csv::{CSVFile, CSVItem};
+
+// How many rows to load at a time when the search gets past the loaded ones.
+const LOAD_CHUNK: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Forward,
+    Backward,
+}
+
+impl Direction {
+    pub fn reversed(self) -> Self {
+        match self {
+            Direction::Forward => Direction::Backward,
+            Direction::Backward => Direction::Forward,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SearchOptions {
+    pub ignore_case: bool,
+    pub whole_cell: bool,
+    pub regex: bool,
+}
+
+impl SearchOptions {
+    pub fn describe(&self) -> String {
+        let flag = |enabled: bool, name: &str| if enabled { format!("[{}]", name) } else { name.to_owned() };
+        format!("{} {} {}", flag(self.ignore_case, "^T ignore case"), flag(self.whole_cell, "^W whole cell"), flag(self.regex, "^X regex"))
+    }
+}
+
+#[derive(Debug)]
+pub struct Search {
+    pattern: String,
+    options: SearchOptions,
+    direction: Direction,
+    regex: Regex, // Plain text patterns are escaped, so everything goes through the same matcher
+}
+
+impl Search {
+    pub fn new(pattern: &str, options: SearchOptions, direction: Direction) -> Result<Self, regex::Error> {
+        let body = if options.regex { pattern.to_owned() } else { regex::escape(pattern) };
+        let body = if options.whole_cell { format!("^(?:{})$", body) } else { body };
+        let regex = RegexBuilder::new(&body)
+            .case_insensitive(options.ignore_case)
+            .build()?;
+        Ok(Search { pattern: pattern.to_owned(), options, direction, regex })
+    }
+
+    pub fn pattern(&self) -> &str {
+        self.pattern.as_str()
+    }
+
+    pub fn options(&self) -> SearchOptions {
+        self.options
+    }
+
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    pub fn matches(&self, item: &CSVItem) -> bool {
+        !self.pattern.is_empty() && self.regex.is_match(&item.text())
+    }
+
+    fn match_in_row(&self, csv: &CSVFile, row_index: usize, columns: impl Iterator<Item=usize>) -> Option<usize> {
+        columns.into_iter().find(|&column_index| {
+            csv.get_column(column_index)
+                .and_then(|column| column.value(row_index))
+                .is_some_and(|item| self.matches(item))
+        })
+    }
+
+    // Checks the given columns of the row displayed at `row`, where `order` lists the rows in
+    // display order (if they are not in file order).
+    fn match_at(&self, csv: &mut CSVFile, order: Option<&[usize]>, row: usize, columns: impl Iterator<Item=usize>, direction: Direction, loading: &mut Loading) -> Option<usize> {
+        let row_index = order.map_or(row, |order| order[row]);
+        if !loading.load(csv, row_index, direction) {
+            return None;
+        }
+        self.match_in_row(csv, row_index, columns)
+    }
+
+    // Finds the nearest matching cell after (or before) the `from` cell, wrapping around the ends of
+    // the file. Rows are visited in display order, given by `order` if the rows are not in file
+    // order. Positions are display positions. Before loading more rows, asks `load`, which can
+    // report progress and returns false to go on among the loaded rows only.
+    pub fn find(&self, csv: &mut CSVFile, order: Option<&[usize]>, from: (usize, usize), direction: Direction, load: &mut dyn FnMut(&CSVFile) -> bool) -> Option<(usize, usize)> {
+        if self.pattern.is_empty() || order.is_some_and(|order| from.0 >= order.len()) {
+            return None;
+        }
+
+        let (from_row, from_column) = from;
+        let column_count = csv.column_count();
+        let row_count = |csv: &CSVFile| order.map_or(csv.row_count(), |order| order.len());
+        let mut loading = Loading { load, allowed: true };
+        let loading = &mut loading;
+
+        match direction {
+            Direction::Forward => {
+                if let Some(column) = self.match_at(csv, order, from_row, from_column + 1..column_count, direction, loading) {
+                    return Some((from_row, column));
+                }
+                let mut row = from_row + 1;
+                loop {
+                    if order.is_none() && !loading.load(csv, row, direction) {
+                        break;
+                    }
+                    if row >= row_count(csv) {
+                        break;
+                    }
+                    if let Some(column) = self.match_at(csv, order, row, 0..column_count, direction, loading) {
+                        return Some((row, column));
+                    }
+                    row += 1;
+                }
+                for row in 0..from_row {
+                    if let Some(column) = self.match_at(csv, order, row, 0..column_count, direction, loading) {
+                        return Some((row, column));
+                    }
+                }
+                self.match_at(csv, order, from_row, 0..from_column + 1, direction, loading).map(|column| (from_row, column))
+            }
+            Direction::Backward => {
+                if let Some(column) = self.match_at(csv, order, from_row, (0..from_column).rev(), direction, loading) {
+                    return Some((from_row, column));
+                }
+                for row in (0..from_row).rev() {
+                    if let Some(column) = self.match_at(csv, order, row, (0..column_count).rev(), direction, loading) {
+                        return Some((row, column));
+                    }
+                }
+                let row_count = match order {
+                    Some(order) => order.len(),
+                    None if loading.allows(csv) => csv.count_all_rows(),
+                    None => csv.row_count(),
+                };
+                for row in (from_row + 1..row_count).rev() {
+                    if let Some(column) = self.match_at(csv, order, row, (0..column_count).rev(), direction, loading) {
+                        return Some((row, column));
+                    }
+                }
+                self.match_at(csv, order, from_row, (from_column..column_count).rev(), direction, loading).map(|column| (from_row, column))
+            }
+        }
+    }
+
+    // Counts matches among the loaded rows. Returns the number of matches and the 1-based position
+    // of the `at` cell (a display position) among them, if it is a match.
+    pub fn count(&self, csv: &CSVFile, order: Option<&[usize]>, at: (usize, usize)) -> (usize, Option<usize>) {
+        let rows: Vec<(usize, usize)> = match order {
+            Some(order) => order.iter().cloned().enumerate().collect(),
+            None => csv.loaded_rows().map(|row_index| (row_index, row_index)).collect(),
+        };
+
+        let mut count = 0;
+        let mut position = None;
+        for (row, row_index) in rows {
+            for column_index in 0..csv.column_count() {
+                let matches = csv.get_column(column_index)
+                    .and_then(|column| column.value(row_index))
+                    .is_some_and(|item| self.matches(item));
+                if matches {
+                    count += 1;
+                    if (row, column_index) == at {
+                        position = Some(count);
+                    }
+                }
+            }
+        }
+        (count, position)
+    }
+}
+
+// Loads rows as a search gets to them, for as long as `load` lets it.
+struct Loading<'a> {
+    load: &'a mut dyn FnMut(&CSVFile) -> bool,
+    allowed: bool, // Until `load` says no once
+}
+
+impl Loading<'_> {
+    fn allows(&mut self, csv: &CSVFile) -> bool {
+        self.allowed = self.allowed && (self.load)(csv);
+        self.allowed
+    }
+
+    // Whether the row is loaded, after loading it along with the rows next to it if it can.
+    fn load(&mut self, csv: &mut CSVFile, row_index: usize, direction: Direction) -> bool {
+        if csv.loaded_rows().contains(&row_index) {
+            return true;
+        }
+        if csv.total_row_count().is_some_and(|total| row_index >= total) || !self.allows(csv) {
+            return false;
+        }
+        match direction {
+            Direction::Forward => csv.ensure_loaded(row_index..row_index + LOAD_CHUNK),
+            Direction::Backward => csv.ensure_loaded(row_index.saturating_sub(LOAD_CHUNK)..row_index + 1),
+        }
+        csv.loaded_rows().contains(&row_index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::csv::tests::unloaded;
+
+    fn partly_loaded(rows: usize) -> CSVFile {
+        let text: String = (0..5000).map(|row| format!("{}\n", row)).collect();
+        let mut csv = unloaded(&format!("n\n{}", text));
+        csv.ensure_loaded(0..rows);
+        csv
+    }
+
+    #[test]
+    fn finds_past_the_loaded_rows_if_allowed() {
+        let mut csv = partly_loaded(10);
+        let search = Search::new("4321", SearchOptions::default(), Direction::Forward).unwrap();
+        let mut asked = 0;
+        let found = search.find(&mut csv, None, (0, 0), Direction::Forward, &mut |_| { asked += 1; true });
+        assert_eq!(found, Some((4321, 0)));
+        assert_eq!(asked, 5);
+    }
+
+    #[test]
+    fn stays_among_the_loaded_rows_if_not_allowed() {
+        let mut csv = partly_loaded(10);
+        let search = Search::new("4321", SearchOptions::default(), Direction::Forward).unwrap();
+        assert_eq!(search.find(&mut csv, None, (0, 0), Direction::Forward, &mut |_| false), None);
+        assert_eq!(csv.loaded_rows(), 0..10);
+
+        let search = Search::new("7", SearchOptions::default(), Direction::Backward).unwrap();
+        assert_eq!(search.find(&mut csv, None, (3, 0), Direction::Backward, &mut |_| false), Some((7, 0)));
+        assert_eq!(csv.loaded_rows(), 0..10);
+    }
+
+    #[test]
+    fn stops_loading_once_told_to() {
+        let mut csv = partly_loaded(10);
+        let search = Search::new("4321", SearchOptions::default(), Direction::Forward).unwrap();
+        let mut asked = 0;
+        let found = search.find(&mut csv, None, (0, 0), Direction::Forward, &mut |_| { asked += 1; asked < 3 });
+        assert_eq!(found, None);
+        assert_eq!((asked, csv.loaded_rows()), (3, 0..2010));
+    }
+}
@@ -0,0 +1,124 @@
+use std::cmp::Ordering;
+
+use crate::csv::{CSVColumn, CSVFile};
+use crate::types::{parse_boolean, parse_date, parse_number, ColumnType, ValueType};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+    Ascending,
+    Descending,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SortKey {
+    pub column: usize,
+    pub order: SortOrder,
+}
+
+impl SortKey {
+    pub fn arrow(&self) -> &'static str {
+        match self.order {
+            SortOrder::Ascending => "↑",
+            SortOrder::Descending => "↓",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Numeric,
+    Temporal,
+    Boolean,
+    Lexical,
+}
+
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+enum SortValue {
+    Number(f64),
+    Date([u32; 7]),
+    Boolean(bool),
+    Text(String), // Also values that do not parse as the type of their column, sorted after those that do
+    Missing, // Empty cells and short rows, always sorted last
+}
+
+fn cell_text(column: &CSVColumn, row_index: usize) -> Option<String> {
+    column.value(row_index).map(|item| item.text()).filter(|text| !text.trim().is_empty())
+}
+
+// A mixed column compares as whichever typed values are most common in it, and the rest as text.
+fn comparison_for(column: &CSVColumn) -> Comparison {
+    let tally = column.type_tally();
+    match column.column_type() {
+        column_type if column_type.is_numeric() => Comparison::Numeric,
+        column_type if column_type.is_temporal() => Comparison::Temporal,
+        ColumnType::Boolean => Comparison::Boolean,
+        ColumnType::Mixed => {
+            let numbers = tally.count(ValueType::Integer) + tally.count(ValueType::Float);
+            let dates = tally.count(ValueType::Date) + tally.count(ValueType::DateTime);
+            let booleans = tally.count(ValueType::Boolean);
+            let texts = tally.count(ValueType::String);
+            let most = numbers.max(dates).max(booleans).max(texts);
+            if most == texts {
+                Comparison::Lexical
+            } else if most == numbers {
+                Comparison::Numeric
+            } else if most == dates {
+                Comparison::Temporal
+            } else {
+                Comparison::Boolean
+            }
+        }
+        _ => Comparison::Lexical,
+    }
+}
+
+fn sort_value(column: &CSVColumn, row_index: usize, comparison: Comparison) -> SortValue {
+    let text = match cell_text(column, row_index) {
+        Some(text) => text,
+        None => return SortValue::Missing,
+    };
+    match comparison {
+        Comparison::Numeric => parse_number(&text).map_or(SortValue::Text(text), SortValue::Number),
+        Comparison::Temporal => parse_date(&text).map_or(SortValue::Text(text), SortValue::Date),
+        Comparison::Boolean => parse_boolean(&text).map_or(SortValue::Text(text), SortValue::Boolean),
+        Comparison::Lexical => SortValue::Text(text),
+    }
+}
+
+fn compare(left: &SortValue, right: &SortValue, order: SortOrder) -> Ordering {
+    match (left, right) {
+        (SortValue::Missing, SortValue::Missing) => Ordering::Equal,
+        (SortValue::Missing, _) => Ordering::Greater,
+        (_, SortValue::Missing) => Ordering::Less,
+        (left, right) => {
+            let ordering = left.partial_cmp(right).unwrap_or(Ordering::Equal);
+            match order {
+                SortOrder::Ascending => ordering,
+                SortOrder::Descending => ordering.reverse(),
+            }
+        }
+    }
+}
+
+// Returns `rows` ordered by the given keys, the first key being the most significant. The sort is
+// stable, so rows that compare equal keep their relative order. The file itself is not reordered.
+pub fn sort_rows(csv: &CSVFile, rows: Vec<usize>, keys: &[SortKey]) -> Vec<usize> {
+    let values: Vec<(SortOrder, Vec<SortValue>)> = keys.iter()
+        .filter_map(|key| {
+            let column = csv.get_column(key.column)?;
+            let comparison = comparison_for(column);
+            log::info!("Sorting by column {} ({}) compared as {:?}", key.column, column.column_type().name(), comparison);
+            let values = rows.iter().map(|&row_index| sort_value(column, row_index, comparison)).collect();
+            Some((key.order, values))
+        })
+        .collect();
+
+    let mut positions: Vec<usize> = (0..rows.len()).collect();
+    positions.sort_by(|&left, &right| {
+        values.iter()
+            .map(|(order, values)| compare(&values[left], &values[right], *order))
+            .find(|ordering| *ordering != Ordering::Equal)
+            .unwrap_or(Ordering::Equal)
+    });
+    positions.into_iter().map(|position| rows[position]).collect()
+}
@@ -0,0 +1,161 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::csv::CSVColumn;
+use crate::types::{parse_date, parse_number, ColumnType};
+
+// How many values to go through between checks whether the computation should go on.
+const PROGRESS_INTERVAL: usize = 10_000;
+
+// How many of the most frequent values to list.
+const TOP_VALUES: usize = 10;
+
+#[derive(Debug)]
+pub struct NumericStatistics {
+    pub mean: f64,
+    pub median: f64,
+    pub standard_deviation: f64,
+}
+
+#[derive(Debug)]
+pub struct ColumnStatistics {
+    pub column_type: ColumnType,
+    pub count: usize,
+    pub empty: usize,
+    pub distinct: usize,
+    pub min: Option<String>,
+    pub max: Option<String>,
+    pub numeric: Option<NumericStatistics>,
+    pub most_frequent: Vec<(String, usize)>,
+    pub min_length: Option<usize>,
+    pub max_length: Option<usize>,
+}
+
+// Keeps track of the smallest and largest value according to the type of the column.
+fn compare_values(left: &str, right: &str, column_type: ColumnType) -> Ordering {
+    let typed = if column_type.is_numeric() {
+        parse_number(left).zip(parse_number(right)).and_then(|(left, right)| left.partial_cmp(&right))
+    } else if column_type.is_temporal() {
+        parse_date(left).zip(parse_date(right)).map(|(left, right)| left.cmp(&right))
+    } else {
+        None
+    };
+    typed.unwrap_or_else(|| left.cmp(right))
+}
+
+pub fn format_number(number: f64) -> String {
+    if number.fract() == 0.0 && number.abs() < 1e15 {
+        format!("{}", number as i64)
+    } else {
+        let text = format!("{:.4}", number);
+        text.trim_end_matches('0').trim_end_matches('.').to_owned()
+    }
+}
+
+impl ColumnStatistics {
+    // Goes through the values of the column in `rows`, which have to be loaded. Values missing from
+    // short rows count as empty. `progress` is called every now and then with the number of values
+    // done and the number of all values, and returns false to cancel.
+    pub fn compute(column: &CSVColumn, rows: &[usize], mut progress: impl FnMut(usize, usize) -> bool) -> Option<Self> {
+        let column_type = column.column_type();
+        let total = rows.len();
+
+        let mut empty = 0;
+        let mut frequencies: HashMap<String, usize> = HashMap::new();
+        let mut numbers: Vec<f64> = Vec::new();
+        let mut min: Option<String> = None;
+        let mut max: Option<String> = None;
+        let mut min_length: Option<usize> = None;
+        let mut max_length: Option<usize> = None;
+
+        for (done, &row_index) in rows.iter().enumerate() {
+            if done % PROGRESS_INTERVAL == 0 && done > 0 && !progress(done, total) {
+                return None;
+            }
+
+            let text = column.value(row_index).map_or_else(String::new, |item| item.text());
+            if text.trim().is_empty() {
+                empty += 1;
+                continue;
+            }
+
+            let length = text.graphemes(true).count();
+            min_length = Some(min_length.map_or(length, |min_length| min_length.min(length)));
+            max_length = Some(max_length.map_or(length, |max_length| max_length.max(length)));
+
+            if column_type.is_numeric() {
+                if let Some(number) = parse_number(&text) {
+                    numbers.push(number);
+                }
+            }
+
+            if min.as_ref().map_or(true, |min| compare_values(&text, min, column_type) == Ordering::Less) {
+                min = Some(text.clone());
+            }
+            if max.as_ref().map_or(true, |max| compare_values(&text, max, column_type) == Ordering::Greater) {
+                max = Some(text.clone());
+            }
+
+            *frequencies.entry(text).or_insert(0) += 1;
+        }
+
+        let numeric = if numbers.is_empty() {
+            None
+        } else {
+            numbers.sort_by(|left, right| left.partial_cmp(right).unwrap_or(Ordering::Equal));
+            let count = numbers.len() as f64;
+            let mean = numbers.iter().sum::<f64>() / count;
+            let middle = numbers.len() / 2;
+            let median = if numbers.len() % 2 == 0 { (numbers[middle - 1] + numbers[middle]) / 2.0 } else { numbers[middle] };
+            let variance = if numbers.len() > 1 {
+                numbers.iter().map(|number| (number - mean).powi(2)).sum::<f64>() / (count - 1.0)
+            } else {
+                0.0
+            };
+            Some(NumericStatistics { mean, median, standard_deviation: variance.sqrt() })
+        };
+
+        let distinct = frequencies.len();
+        let mut most_frequent: Vec<(String, usize)> = frequencies.into_iter().collect();
+        most_frequent.sort_by(|(left_value, left_count), (right_value, right_count)| {
+            right_count.cmp(left_count).then_with(|| left_value.cmp(right_value))
+        });
+        most_frequent.truncate(TOP_VALUES);
+
+        Some(ColumnStatistics {
+            column_type, count: total, empty, distinct, min, max, numeric, most_frequent, min_length, max_length,
+        })
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        let or_none = |value: &Option<String>| value.clone().unwrap_or_else(|| "-".to_owned());
+        let length = |length: Option<usize>| length.map_or_else(|| "-".to_owned(), |length| length.to_string());
+
+        let mut lines = vec![
+            format!("type:      {}", self.column_type.name()),
+            format!("count:     {}", self.count),
+            format!("empty:     {}", self.empty),
+            format!("distinct:  {}", self.distinct),
+            format!("min:       {}", or_none(&self.min).replace('\n', "↵")),
+            format!("max:       {}", or_none(&self.max).replace('\n', "↵")),
+        ];
+        if let Some(numeric) = &self.numeric {
+            lines.push(format!("mean:      {}", format_number(numeric.mean)));
+            lines.push(format!("median:    {}", format_number(numeric.median)));
+            lines.push(format!("stddev:    {}", format_number(numeric.standard_deviation)));
+        }
+        lines.push(format!("length:    {} to {}", length(self.min_length), length(self.max_length)));
+
+        if !self.most_frequent.is_empty() {
+            lines.push(String::new());
+            lines.push("most frequent:".to_owned());
+            let count_width = self.most_frequent.iter().map(|(_, count)| count.to_string().len()).max().unwrap_or(0);
+            for (value, count) in &self.most_frequent {
+                lines.push(format!("  {:>width$}  {}", count, value.replace('\n', "↵"), width = count_width));
+            }
+        }
+        lines
+    }
+}
@@ -0,0 +1,277 @@
+// How many values of a column are looked at to decide its type. Columns can be much longer than
+// that, and the values are only classified as they load anyway.
+pub const TYPE_SAMPLE_SIZE: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueType {
+    Empty,
+    Integer,
+    Float,
+    Boolean,
+    Date,
+    DateTime,
+    String,
+}
+
+impl ValueType {
+    const ALL: [ValueType; 7] = [
+        ValueType::Empty, ValueType::Integer, ValueType::Float, ValueType::Boolean,
+        ValueType::Date, ValueType::DateTime, ValueType::String,
+    ];
+
+    pub fn of(text: &str) -> Self {
+        let text = text.trim();
+        if text.is_empty() {
+            ValueType::Empty
+        } else if parse_integer(text).is_some() {
+            ValueType::Integer
+        } else if parse_number(text).is_some() {
+            ValueType::Float
+        } else if parse_boolean(text).is_some() {
+            ValueType::Boolean
+        } else if parse_date(text).is_some() {
+            if text.len() == 10 { ValueType::Date } else { ValueType::DateTime }
+        } else {
+            ValueType::String
+        }
+    }
+
+    fn position(self) -> usize {
+        ValueType::ALL.iter().position(|value_type| *value_type == self).unwrap()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnType {
+    Empty,
+    Integer,
+    Float,
+    Boolean,
+    Date,
+    DateTime,
+    String,
+    Mixed,
+}
+
+impl ColumnType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ColumnType::Empty => "empty",
+            ColumnType::Integer => "integer",
+            ColumnType::Float => "float",
+            ColumnType::Boolean => "boolean",
+            ColumnType::Date => "date",
+            ColumnType::DateTime => "datetime",
+            ColumnType::String => "string",
+            ColumnType::Mixed => "mixed",
+        }
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        matches!(self, ColumnType::Integer | ColumnType::Float)
+    }
+
+    pub fn is_temporal(&self) -> bool {
+        matches!(self, ColumnType::Date | ColumnType::DateTime)
+    }
+}
+
+// Counts the types of the first values of a column. Empty values do not count against any type.
+#[derive(Debug, Default, Clone)]
+pub struct TypeTally {
+    counts: [usize; 7],
+    sampled: usize,
+}
+
+impl TypeTally {
+    pub fn add(&mut self, text: &str) {
+        if self.sampled >= TYPE_SAMPLE_SIZE {
+            return;
+        }
+        self.counts[ValueType::of(text).position()] += 1;
+        self.sampled += 1;
+    }
+
+    // Takes back a value that was added, when it is edited or deleted.
+    pub fn remove(&mut self, text: &str) {
+        let position = ValueType::of(text).position();
+        if self.counts[position] > 0 {
+            self.counts[position] -= 1;
+            self.sampled -= 1;
+        }
+    }
+
+    pub fn count(&self, value_type: ValueType) -> usize {
+        self.counts[value_type.position()]
+    }
+
+    pub fn column_type(&self) -> ColumnType {
+        let present: Vec<ValueType> = ValueType::ALL.iter().cloned()
+            .filter(|value_type| *value_type != ValueType::Empty && self.count(*value_type) > 0)
+            .collect();
+        let only = |allowed: &[ValueType]| present.iter().all(|value_type| allowed.contains(value_type));
+
+        if present.is_empty() {
+            ColumnType::Empty
+        } else if only(&[ValueType::Integer]) {
+            ColumnType::Integer
+        } else if only(&[ValueType::Integer, ValueType::Float]) {
+            ColumnType::Float
+        } else if only(&[ValueType::Boolean]) {
+            ColumnType::Boolean
+        } else if only(&[ValueType::Date]) {
+            ColumnType::Date
+        } else if only(&[ValueType::Date, ValueType::DateTime]) {
+            ColumnType::DateTime
+        } else if only(&[ValueType::String]) {
+            ColumnType::String
+        } else {
+            ColumnType::Mixed
+        }
+    }
+}
+
+pub fn parse_integer(text: &str) -> Option<i64> {
+    text.trim().parse::<i64>().ok()
+}
+
+pub fn parse_number(text: &str) -> Option<f64> {
+    let text = text.trim();
+    if text.is_empty() {
+        return None;
+    }
+    text.parse::<f64>().ok().filter(|number| number.is_finite())
+}
+
+pub fn parse_boolean(text: &str) -> Option<bool> {
+    match text.trim().to_lowercase().as_str() {
+        "true" | "yes" => Some(true),
+        "false" | "no" => Some(false),
+        _ => None,
+    }
+}
+
+// Accepts ISO 8601-like dates and date-times: `2021-03-04`, `2021/03/04`, `2021-03-04 05:06`,
+// `2021-03-04T05:06:07.890Z`. Time zones are ignored. Returns year, month, day, hour, minute,
+// second and nanosecond.
+pub fn parse_date(text: &str) -> Option<[u32; 7]> {
+    let text = text.trim();
+    let bytes = text.as_bytes();
+    if bytes.len() < 10 || !(bytes[4] == b'-' || bytes[4] == b'/') || bytes[7] != bytes[4] {
+        return None;
+    }
+    let number = |range: std::ops::Range<usize>| -> Option<u32> {
+        let digits = text.get(range)?;
+        if !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+            return None;
+        }
+        digits.parse().ok()
+    };
+
+    let mut date = [0u32; 7];
+    date[0] = number(0..4)?;
+    date[1] = number(5..7)?;
+    date[2] = number(8..10)?;
+    if !(1..=12).contains(&date[1]) || !(1..=31).contains(&date[2]) {
+        return None;
+    }
+
+    let time = &text[10..];
+    if time.is_empty() {
+        return Some(date);
+    }
+    let time = time.strip_prefix('T').or_else(|| time.strip_prefix(' '))?;
+    let time = time.trim_end_matches('Z');
+    let time = match time.find(['+', '-']) {
+        Some(zone) => &time[..zone],
+        None => time,
+    };
+
+    let mut parts = time.splitn(3, ':');
+    date[3] = parts.next()?.parse().ok().filter(|hour| *hour < 24)?;
+    date[4] = parts.next()?.parse().ok().filter(|minute| *minute < 60)?;
+    if let Some(seconds) = parts.next() {
+        let mut seconds = seconds.splitn(2, '.');
+        date[5] = seconds.next()?.parse().ok().filter(|second| *second <= 60)?;
+        if let Some(fraction) = seconds.next() {
+            let digits: String = fraction.chars().chain(std::iter::repeat('0')).take(9).collect();
+            date[6] = digits.parse().ok()?;
+        }
+    }
+    Some(date)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tally(values: &[&str]) -> ColumnType {
+        let mut tally = TypeTally::default();
+        for value in values {
+            tally.add(value);
+        }
+        tally.column_type()
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!(parse_date("2021-03-04"), Some([2021, 3, 4, 0, 0, 0, 0]));
+        assert_eq!(parse_date(" 2021/03/04 "), Some([2021, 3, 4, 0, 0, 0, 0]));
+        assert_eq!(parse_date("2021-03-04 05:06"), Some([2021, 3, 4, 5, 6, 0, 0]));
+        assert_eq!(parse_date("2021-03-04T05:06:07.89Z"), Some([2021, 3, 4, 5, 6, 7, 890_000_000]));
+        assert_eq!(parse_date("2021-03-04T05:06:07+02:00"), Some([2021, 3, 4, 5, 6, 7, 0]));
+        assert_eq!(parse_date("2021-03-04T05:06:07-0500"), Some([2021, 3, 4, 5, 6, 7, 0]));
+    }
+
+    #[test]
+    fn not_dates() {
+        assert_eq!(parse_date("2021-03/04"), None);
+        assert_eq!(parse_date("2021-13-04"), None);
+        assert_eq!(parse_date("2021-03-00"), None);
+        assert_eq!(parse_date("21-03-04"), None);
+        assert_eq!(parse_date("2021-03-04X05:06"), None);
+        assert_eq!(parse_date("2021-03-04 24:00"), None);
+        assert_eq!(parse_date("2021-03-04 05"), None);
+        assert_eq!(parse_date("2021-0a-04"), None);
+        assert_eq!(parse_date("2021-03-ö4"), None);
+    }
+
+    #[test]
+    fn value_types() {
+        assert_eq!(ValueType::of(""), ValueType::Empty);
+        assert_eq!(ValueType::of("  "), ValueType::Empty);
+        assert_eq!(ValueType::of("007"), ValueType::Integer);
+        assert_eq!(ValueType::of("-12"), ValueType::Integer);
+        assert_eq!(ValueType::of("1.5"), ValueType::Float);
+        assert_eq!(ValueType::of("1e3"), ValueType::Float);
+        assert_eq!(ValueType::of("NaN"), ValueType::String);
+        assert_eq!(ValueType::of("inf"), ValueType::String);
+        assert_eq!(ValueType::of("Yes"), ValueType::Boolean);
+        assert_eq!(ValueType::of("false"), ValueType::Boolean);
+        assert_eq!(ValueType::of("2021-03-04"), ValueType::Date);
+        assert_eq!(ValueType::of("2021-03-04 05:06"), ValueType::DateTime);
+        assert_eq!(ValueType::of("hamster"), ValueType::String);
+    }
+
+    #[test]
+    fn column_types() {
+        assert_eq!(tally(&[]), ColumnType::Empty);
+        assert_eq!(tally(&["", " "]), ColumnType::Empty);
+        assert_eq!(tally(&["1", "", "007"]), ColumnType::Integer);
+        assert_eq!(tally(&["1", "2.5"]), ColumnType::Float);
+        assert_eq!(tally(&["yes", "no", ""]), ColumnType::Boolean);
+        assert_eq!(tally(&["2021-03-04", "2021/03/05"]), ColumnType::Date);
+        assert_eq!(tally(&["2021-03-04", "2021-03-04T05:06"]), ColumnType::DateTime);
+        assert_eq!(tally(&["a", "b"]), ColumnType::String);
+        assert_eq!(tally(&["1", "a"]), ColumnType::Mixed);
+        assert_eq!(tally(&["1", "yes"]), ColumnType::Mixed);
+        assert_eq!(tally(&["2021-03-04", "1"]), ColumnType::Mixed);
+    }
+
+    #[test]
+    fn only_the_first_values_are_sampled() {
+        let mut values = vec!["1"; TYPE_SAMPLE_SIZE];
+        values.push("a");
+        assert_eq!(tally(&values), ColumnType::Integer);
+    }
+}